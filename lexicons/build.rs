@@ -1,102 +1,310 @@
-#![allow(warnings)]
-
 #[path = "build/spec.rs"]
 mod spec;
 
 #[path = "build/casing.rs"]
 mod casing;
 
-use std::path::Path;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
 use std::{env, fs};
 
-use casing::convert_casing_to_pascal;
+use casing::{convert_casing_to_pascal, convert_casing_to_snake};
 use spec::{
-    Lexicon, LexiconDoc, LexiconObject, LexiconPrimitive, LexiconType, LexiconXrpcQueryProc,
+    Lexicon, LexiconDoc, LexiconObject, LexiconSchema, LexiconString, LexiconSubscription,
+    LexiconType, LexiconUnion, LexiconXrpcQueryProc, XrpcBody,
 };
 
+const DERIVE: &str = "#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]\n";
+
+/// Rust keywords which can't be used as identifiers without `r#`.
+const KEYWORDS: &[&str] = &[
+    "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern",
+    "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub",
+    "ref", "return", "self", "Self", "static", "struct", "super", "trait", "true", "type",
+    "unsafe", "use", "where", "while", "abstract", "become", "box", "do", "final", "macro",
+    "override", "priv", "try", "typeof", "unsized", "virtual", "yield",
+];
+
+fn identifier(name: &str) -> String {
+    if KEYWORDS.contains(&name) {
+        format!("r#{name}")
+    } else {
+        name.to_owned()
+    }
+}
+
+/// Doc comment for a generated item.
+fn doc_comment(text: &str) -> String {
+    text.lines()
+        .map(|line| format!("{}\n", format!("/// {line}").trim_end()))
+        .collect()
+}
+
+/// Panics if two values would get the same variant name in the enum generated
+/// for `id`.
+fn check_variants<'a>(id: &str, variants: impl IntoIterator<Item = (&'a str, &'a str)>) {
+    let mut seen = HashMap::new();
+
+    for (variant, value) in variants {
+        if let Some(other) = seen.insert(variant, value) {
+            panic!("Variants for '{other}' and '{value}' in '{id}' are both named '{variant}'");
+        }
+    }
+}
+
+/// The paths of the generated types by lexicon id, for naming definitions and
+/// resolving references between them.
+struct Context {
+    paths: HashMap<String, String>,
+}
+
+impl Context {
+    fn new<'a>(docs: impl IntoIterator<Item = &'a LexiconDoc>) -> Self {
+        let mut paths = HashMap::new();
+
+        for doc in docs {
+            let module = module_path(&doc.id);
+
+            for def in doc.defs.keys() {
+                let id = match def.as_str() {
+                    "main" => doc.id.clone(),
+                    def => format!("{}#{def}", doc.id),
+                };
+
+                paths.insert(id, format!("{module}::{}", type_name(doc, def)));
+            }
+        }
+
+        Self { paths }
+    }
+
+    /// Name of the type generated for a definition.
+    fn name(&self, id: &str) -> &str {
+        let path = &self.paths[id];
+        path.rsplit("::").next().unwrap_or(path)
+    }
+
+    /// Whether a definition in the document `nsid` generates a type `name`.
+    fn is_defined(&self, nsid: &str, name: &str) -> bool {
+        let path = format!("{}::{name}", module_path(nsid));
+        self.paths.values().any(|it| *it == path)
+    }
+
+    /// Path of the type for a reference from the definition `from`, which can
+    /// be relative to its document like `#name`.
+    fn resolve(&self, from: &str, reference: &str) -> &str {
+        let id = absolute_ref(from, reference);
+
+        self.paths
+            .get(&id)
+            .unwrap_or_else(|| panic!("Unresolved reference '{reference}' in '{from}'"))
+    }
+}
+
+/// Path of the module generated for a document.
+fn module_path(nsid: &str) -> String {
+    let mut path = String::from("crate");
+    for segment in nsid.split('.') {
+        path.push_str("::");
+        path.push_str(&convert_casing_to_snake(segment));
+    }
+
+    path
+}
+
+/// The full id of a reference from the definition `from`, as used in `$type`.
+fn absolute_ref(from: &str, reference: &str) -> String {
+    let nsid = from.split('#').next().unwrap_or(from);
+
+    match reference.strip_prefix('#') {
+        Some(name) => format!("{nsid}#{name}"),
+        None => reference.to_owned(),
+    }
+}
+
+/// Name of the type generated for a definition in a document. The main
+/// definition is named after the last segment of the NSID, or `Main` when
+/// another definition already has that name.
+fn type_name(doc: &LexiconDoc, def: &str) -> String {
+    if def != "main" {
+        return convert_casing_to_pascal(def);
+    }
+
+    let name = doc.id.split('.').next_back().map(convert_casing_to_pascal);
+    let name = name.unwrap_or_default();
+    let taken = doc
+        .defs
+        .keys()
+        .any(|it| it != "main" && convert_casing_to_pascal(it) == name);
+
+    if taken {
+        "Main".to_owned()
+    } else {
+        name
+    }
+}
+
 impl Lexicon {
-    fn codegen(&self) -> String {
+    fn codegen(&self, ctx: &Context) -> String {
+        let name = ctx.name(&self.id);
+        let mut docs = self
+            .description
+            .as_deref()
+            .map(doc_comment)
+            .unwrap_or_default();
+
         match &self.typ {
-            LexiconType::Token => todo!(),
-            LexiconType::Object { inner } => {
-                let name = self
-                    .id
-                    .split('.')
-                    .last()
-                    .map(convert_casing_to_pascal)
-                    .unwrap();
-
-                let mut result = String::new();
-                result.push_str(&format!("pub struct {name} {{\n"));
-                result.push_str(&self.codegen_object(inner));
-                result.push_str("}\n");
-                result
+            LexiconType::Token => {
+                let name = convert_casing_to_snake(name).to_uppercase();
+                format!(
+                    "{docs}pub const {name}: crate::Token = crate::Token::new(\"{}\");\n",
+                    self.id
+                )
+            }
+            LexiconType::Record(inner) => {
+                if let Some(key) = &inner.key {
+                    if !docs.is_empty() {
+                        docs.push_str("///\n");
+                    }
+                    docs.push_str(&format!("/// Record key: `{key}`.\n"));
+                }
+
+                docs + &self.codegen_struct(ctx, name, &inner.record)
             }
-            LexiconType::Record { inner: _ } => todo!(),
-            LexiconType::Query { inner } => self.codegen_queryproc(inner),
-            LexiconType::Procedure { inner } => self.codegen_queryproc(inner),
-            LexiconType::Blob => todo!(),
-            LexiconType::Image => todo!(),
-            LexiconType::Video => todo!(),
-            LexiconType::Audio => todo!(),
+            LexiconType::Query(inner) => self.codegen_queryproc(ctx, name, &docs, inner),
+            LexiconType::Procedure(inner) => self.codegen_queryproc(ctx, name, &docs, inner),
+            LexiconType::Subscription(inner) => self.codegen_subscription(ctx, name, &docs, inner),
+            LexiconType::Schema(schema) => docs + &self.codegen_schema(ctx, name, schema),
         }
     }
 
-    fn codegen_queryproc(&self, procedure: &LexiconXrpcQueryProc) -> String {
+    /// Types for the parameters, input and output of a method, each documented
+    /// with the method's `docs`.
+    fn codegen_queryproc(
+        &self,
+        ctx: &Context,
+        name: &str,
+        docs: &str,
+        procedure: &LexiconXrpcQueryProc,
+    ) -> String {
         let mut result = String::new();
 
-        // Get the name of the structure
-        let name = self
-            .id
-            .split('.')
-            .last()
-            .map(convert_casing_to_pascal)
-            .unwrap();
-
-        if let Some(body) = &procedure.parameters {
-            result.push_str(&format!("pub struct {name}Params {{\n"));
-            let object = self.codegen_object(&body.properties.clone().into());
-            result.push_str(&object);
-            result.push_str("}\n");
+        if let Some(parameters) = &procedure.parameters {
+            result.push_str(docs);
+            result.push_str(&self.codegen_struct(ctx, &format!("{name}Params"), parameters));
         }
 
-        if let Some(body) = &procedure.input {
-            result.push_str(&format!("pub struct {name}Input {{\n"));
-            let object = self.codegen_object(&body.schema);
-            result.push_str(&object);
-            result.push_str("}\n");
+        if let Some(XrpcBody {
+            schema: Some(schema),
+        }) = &procedure.input
+        {
+            result.push_str(docs);
+            result.push_str(&self.codegen_schema(ctx, &format!("{name}Input"), schema));
         }
 
-        if let Some(body) = &procedure.output {
-            result.push_str(&format!("pub struct {name}Output {{\n"));
-            let object = self.codegen_object(&body.schema);
-            result.push_str(&object);
-            result.push_str("}\n");
+        if let Some(XrpcBody {
+            schema: Some(schema),
+        }) = &procedure.output
+        {
+            result.push_str(docs);
+            result.push_str(&self.codegen_schema(ctx, &format!("{name}Output"), schema));
         }
 
         result
     }
 
-    fn codegen_object(&self, object: &LexiconObject) -> String {
+    fn codegen_subscription(
+        &self,
+        ctx: &Context,
+        name: &str,
+        docs: &str,
+        subscription: &LexiconSubscription,
+    ) -> String {
+        let mut result = String::new();
+
+        if let Some(parameters) = &subscription.parameters {
+            result.push_str(docs);
+            result.push_str(&self.codegen_struct(ctx, &format!("{name}Params"), parameters));
+        }
+
+        if let Some(message) = &subscription.message {
+            result.push_str(docs);
+            result.push_str(&self.codegen_schema(ctx, &format!("{name}Message"), message));
+        }
+
+        result
+    }
+
+    /// A struct for objects, an enum for strings with known values, or an alias
+    /// for any other schema.
+    fn codegen_schema(&self, ctx: &Context, name: &str, schema: &LexiconSchema) -> String {
+        match schema {
+            LexiconSchema::Object(object) => self.codegen_struct(ctx, name, object),
+            LexiconSchema::String(string) if string.has_values() => {
+                self.codegen_string_enum(name, string)
+            }
+            LexiconSchema::Union(union) => self.codegen_union(ctx, name, union),
+            schema => {
+                let mut types = String::new();
+                let typ = self.codegen_type(ctx, &format!("{name}Item"), schema, &mut types);
+                format!("pub type {name} = {typ};\n{types}")
+            }
+        }
+    }
+
+    fn codegen_struct(&self, ctx: &Context, name: &str, object: &LexiconObject) -> String {
+        // Types for the properties, like enums, go after the struct.
+        let mut types = String::new();
+
+        let mut result = String::new();
+        result.push_str(DERIVE);
+        result.push_str(&format!("pub struct {name} {{\n"));
+        result.push_str(&self.codegen_object(ctx, name, object, &mut types));
+        result.push_str("}\n");
+        result.push_str(&types);
+        result
+    }
+
+    fn codegen_object(
+        &self,
+        ctx: &Context,
+        name: &str,
+        object: &LexiconObject,
+        types: &mut String,
+    ) -> String {
+        let struct_name = name;
         let mut result = String::new();
 
         for (name, prop) in object.properties.iter() {
+            let field = convert_casing_to_snake(name);
+            if field != *name {
+                result.push_str(&format!("    #[serde(rename = \"{name}\")]\n"));
+            }
+
+            // Required properties which are nullable are still serialized.
+            let required = object.required.contains(name);
+            if !required {
+                result
+                    .push_str("    #[serde(default, skip_serializing_if = \"Option::is_none\")]\n");
+            }
+
             result.push_str("    pub ");
-            result.push_str(name);
+            result.push_str(&identifier(&field));
             result.push_str(": ");
 
-            let typ = match prop {
-                LexiconPrimitive::Boolean => "bool",
-                LexiconPrimitive::Number => "f64",
-                LexiconPrimitive::Integer => "i64",
-                LexiconPrimitive::String { enum_values: _ } => "String",
-            };
+            // Named after the struct and property, unless a definition in the
+            // document already has that name.
+            let mut type_name = format!("{struct_name}{}", convert_casing_to_pascal(name));
+            if ctx.is_defined(self.id.split('#').next().unwrap_or(&self.id), &type_name) {
+                type_name.push_str("Kind");
+            }
+            let typ = self.codegen_type(ctx, &type_name, prop, types);
 
-            if object.required.contains(name) {
-                result.push_str(typ);
+            if required && !object.nullable.contains(name) {
+                result.push_str(&typ);
             } else {
                 result.push_str("Option<");
-                result.push_str(typ);
+                result.push_str(&typ);
                 result.push('>');
             }
 
@@ -105,18 +313,302 @@ impl Lexicon {
 
         result
     }
+
+    /// The Rust type for a schema. Types which have to be generated for it are
+    /// named `name` and added to `types`.
+    fn codegen_type(
+        &self,
+        ctx: &Context,
+        name: &str,
+        schema: &LexiconSchema,
+        types: &mut String,
+    ) -> String {
+        match schema {
+            LexiconSchema::Null => "()".to_owned(),
+            LexiconSchema::Boolean => "bool".to_owned(),
+            LexiconSchema::Integer => "i64".to_owned(),
+            LexiconSchema::String(string) if string.has_values() => {
+                types.push_str(&self.codegen_string_enum(name, string));
+                name.to_owned()
+            }
+            LexiconSchema::String(_) => "String".to_owned(),
+            LexiconSchema::Bytes => "crate::Bytes".to_owned(),
+            LexiconSchema::CidLink => "crate::CidLink".to_owned(),
+            LexiconSchema::Array(items) => {
+                format!("Vec<{}>", self.codegen_type(ctx, name, items, types))
+            }
+            LexiconSchema::Ref(reference) => ctx.resolve(&self.id, reference).to_owned(),
+            LexiconSchema::Union(union) => {
+                types.push_str(&self.codegen_union(ctx, name, union));
+                name.to_owned()
+            }
+            LexiconSchema::Blob => "crate::Blob".to_owned(),
+            LexiconSchema::Object(object) => {
+                types.push_str(&self.codegen_struct(ctx, name, object));
+                name.to_owned()
+            }
+            LexiconSchema::Unknown => "serde_json::Value".to_owned(),
+        }
+    }
+
+    /// An enum tagged by `$type` with a variant for each of the refs. Open
+    /// unions can get new types, which end up in `Unknown`.
+    fn codegen_union(&self, ctx: &Context, name: &str, union: &LexiconUnion) -> String {
+        let ids = union
+            .refs
+            .iter()
+            .map(|it| absolute_ref(&self.id, it))
+            .collect::<Vec<_>>();
+
+        // Refs are named after their definition, or the whole NSID when that
+        // isn't unique, like for the `#view` of each embed.
+        let short_names = ids.iter().map(|id| {
+            let name = match id.split_once('#') {
+                Some((_, name)) => name,
+                None => id.split('.').next_back().unwrap_or(id),
+            };
+            convert_casing_to_pascal(name)
+        });
+        let short_names = short_names.collect::<Vec<_>>();
+
+        let unique = short_names
+            .iter()
+            .all(|it| short_names.iter().filter(|other| *other == it).count() == 1);
+
+        let variants = ids.iter().zip(short_names).map(|(id, short_name)| {
+            let variant = if unique {
+                short_name
+            } else {
+                let (nsid, def) = id.split_once('#').unwrap_or((id, ""));
+                let document = nsid.split('.').next_back().unwrap_or(nsid);
+                convert_casing_to_pascal(&format!("{document}_{def}"))
+            };
+
+            if variant == "Unknown" {
+                panic!("Can't name the variant for '{id}' in '{}'", self.id);
+            }
+
+            variant
+        });
+        let variants = variants.collect::<Vec<_>>();
+        check_variants(
+            &self.id,
+            variants
+                .iter()
+                .zip(&ids)
+                .map(|(v, id)| (v.as_str(), id.as_str())),
+        );
+
+        let mut result = String::new();
+        result.push_str(DERIVE);
+        result.push_str("#[serde(tag = \"$type\")]\n");
+        result.push_str(&format!("pub enum {name} {{\n"));
+
+        for ((id, reference), variant) in ids.iter().zip(&union.refs).zip(&variants) {
+            // Boxing the variants, as unions are often recursive and their
+            // types differ a lot in size.
+            let typ = ctx.resolve(&self.id, reference);
+            result.push_str(&format!("    #[serde(rename = \"{id}\")]\n"));
+            result.push_str(&format!("    {variant}(Box<{typ}>),\n"));
+        }
+
+        if !union.closed {
+            result.push_str("    #[serde(untagged)]\n");
+            result.push_str("    Unknown(serde_json::Value),\n");
+        }
+
+        result.push_str("}\n");
+        result
+    }
+
+    /// An enum of the values of a string. With `knownValues` other values are
+    /// allowed too, so the enum is non-exhaustive and keeps them in `Other`.
+    fn codegen_string_enum(&self, name: &str, string: &LexiconString) -> String {
+        let nsid = self.id.split('#').next().unwrap_or(&self.id);
+        let (values, open) = match &string.enum_values {
+            Some(values) => (values.clone(), false),
+            // Known values starting with `#` are tokens in the same document.
+            None => {
+                let values = string
+                    .known_values
+                    .iter()
+                    .map(|it| match it.strip_prefix('#') {
+                        Some(token) => format!("{nsid}#{token}"),
+                        None => it.clone(),
+                    });
+                (values.collect(), true)
+            }
+        };
+
+        let variants = values
+            .iter()
+            .map(|value| {
+                let variant = value.rsplit(['#', '.']).next().unwrap_or(value);
+                let variant = convert_casing_to_pascal(variant);
+                if variant.is_empty() || variant == "Other" {
+                    panic!("Can't name the variant for '{value}' in '{}'", self.id);
+                }
+
+                (variant, value)
+            })
+            .collect::<Vec<_>>();
+        check_variants(
+            &self.id,
+            variants
+                .iter()
+                .map(|(v, value)| (v.as_str(), value.as_str())),
+        );
+
+        let mut result = String::new();
+
+        if open {
+            result.push_str(
+                "#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize, \
+                 serde::Deserialize)]\n",
+            );
+            result.push_str("#[serde(from = \"String\", into = \"String\")]\n");
+            result.push_str("#[non_exhaustive]\n");
+        } else {
+            result.push_str(
+                "#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, \
+                 serde::Deserialize)]\n",
+            );
+        }
+
+        result.push_str(&format!("pub enum {name} {{\n"));
+        for (variant, value) in variants.iter() {
+            if !open {
+                result.push_str(&format!("    #[serde(rename = \"{value}\")]\n"));
+            }
+            result.push_str(&format!("    {variant},\n"));
+        }
+        if open {
+            result.push_str("    Other(String),\n");
+        }
+        result.push_str("}\n");
+
+        result.push_str(&format!("impl {name} {{\n"));
+        result.push_str("    pub fn as_str(&self) -> &str {\n");
+        result.push_str("        match self {\n");
+        for (variant, value) in variants.iter() {
+            result.push_str(&format!("            Self::{variant} => \"{value}\",\n"));
+        }
+        if open {
+            result.push_str("            Self::Other(value) => value,\n");
+        }
+        result.push_str("        }\n");
+        result.push_str("    }\n");
+        result.push_str("}\n");
+
+        if open {
+            result.push_str(&format!("impl From<String> for {name} {{\n"));
+            result.push_str("    fn from(value: String) -> Self {\n");
+            result.push_str("        match value.as_str() {\n");
+            for (variant, value) in variants.iter() {
+                result.push_str(&format!("            \"{value}\" => Self::{variant},\n"));
+            }
+            result.push_str("            _ => Self::Other(value),\n");
+            result.push_str("        }\n");
+            result.push_str("    }\n");
+            result.push_str("}\n");
+
+            result.push_str(&format!("impl From<{name}> for String {{\n"));
+            result.push_str(&format!("    fn from(value: {name}) -> Self {{\n"));
+            result.push_str("        match value {\n");
+            result.push_str(&format!("            {name}::Other(value) => value,\n"));
+            result.push_str("            value => value.as_str().to_owned(),\n");
+            result.push_str("        }\n");
+            result.push_str("    }\n");
+            result.push_str("}\n");
+        }
+
+        result
+    }
+}
+
+/// Generated code, nested in modules following the NSIDs.
+#[derive(Default)]
+struct Module {
+    code: String,
+    children: BTreeMap<String, Module>,
+}
+
+impl Module {
+    fn insert(&mut self, nsid: &str, code: &str) {
+        let module = nsid.split('.').fold(self, |module, segment| {
+            module
+                .children
+                .entry(convert_casing_to_snake(segment))
+                .or_default()
+        });
+
+        module.code.push_str(code);
+    }
+
+    fn codegen(&self) -> String {
+        let mut result = self.code.clone();
+
+        for (name, module) in self.children.iter() {
+            result.push_str(&format!("pub mod {name} {{\n"));
+            result.push_str(&module.codegen());
+            result.push_str("}\n");
+        }
+
+        result
+    }
+}
+
+/// Every lexicon document under `dir`, sorted by path.
+fn lexicon_files(dir: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            files.extend(lexicon_files(&path));
+        } else if path.extension().is_some_and(|it| it == "json") {
+            files.push(path);
+        }
+    }
+
+    files.sort();
+    files
 }
 
 fn main() {
     let root = env::var("CARGO_MANIFEST_DIR").unwrap();
+    let out_dir = env::var("OUT_DIR").unwrap();
+
+    let in_path = Path::new(&root).join("data");
+    let out_path = Path::new(&out_dir).join("lexicons.rs");
+
+    println!("cargo:rerun-if-changed={}", in_path.display());
+    println!("cargo:rerun-if-changed=build");
 
-    let in_path = Path::new(&root).join("data/");
-    let out_path = Path::new(&root).join("src/");
+    let mut docs = Vec::new();
+    for path in lexicon_files(&in_path) {
+        let lexicon_file = fs::read_to_string(&path).unwrap();
+        let lexicon_file = serde_json::from_str::<LexiconDoc>(&lexicon_file)
+            .unwrap_or_else(|e| panic!("Invalid lexicon '{}': {e}", path.display()));
 
-    let lexicon_file =
-        fs::read_to_string(in_path.join("com/atproto/server/createSession.json")).unwrap();
-    let lexicon_file = serde_json::from_str::<LexiconDoc>(&lexicon_file).unwrap();
-    // println!();
+        let lexicons = lexicon_file
+            .lexicons()
+            .unwrap_or_else(|e| panic!("Invalid lexicon '{}' at {e}", path.display()));
+
+        docs.push((lexicon_file, lexicons));
+    }
+
+    let ctx = Context::new(docs.iter().map(|(doc, _)| doc));
+    let mut root_module = Module::default();
+
+    for (lexicon_file, lexicons) in docs.iter() {
+        let code = lexicons
+            .iter()
+            .map(|it| it.codegen(&ctx))
+            .collect::<String>();
+
+        root_module.insert(&lexicon_file.id, &code);
+    }
 
-    // panic!("{}", lexicon_file.lexicons().first().unwrap().codegen());
+    fs::write(out_path, root_module.codegen()).unwrap();
 }
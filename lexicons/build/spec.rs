@@ -1,153 +1,400 @@
-use std::collections::HashMap;
+use std::collections::BTreeMap;
+use std::fmt;
 
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct LexiconDoc {
-    lexicon: i32,
-    id: String,
-    description: Option<String>,
+    pub lexicon: i32,
+    pub id: String,
+    pub description: Option<String>,
 
-    defs: HashMap<String, Value>,
+    pub defs: BTreeMap<String, Value>,
 }
 
 impl LexiconDoc {
-    pub fn lexicons(&self) -> Vec<Lexicon> {
+    /// The definitions in the document, with their ids set to `nsid` for the
+    /// main definition and `nsid#name` for the others.
+    pub fn lexicons(&self) -> Result<Vec<Lexicon>, SpecError> {
         let mut lexicons = Vec::new();
 
         for (name, def) in self.defs.iter() {
             let mut id = self.id.clone();
             if name != "main" {
+                id.push('#');
                 id.push_str(name);
             }
 
-            // Parsing the lexicon and updating the id
-            // panic!("{:#?}", def.clone());
-            let mut lexicon = serde_json::from_value::<Lexicon>(def.clone()).unwrap();
-            lexicon.id = id;
+            let node = Node::new(def, format!("defs.{name}"));
+            lexicons.push(Lexicon {
+                id,
+                description: node.get("description")?,
+                typ: LexiconType::parse(&node)?,
+            });
+        }
+
+        Ok(lexicons)
+    }
+}
+
+/// A definition which doesn't follow the lexicon spec, with the JSON path to
+/// the offending value.
+#[derive(Clone, Debug)]
+pub struct SpecError {
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for SpecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.message)
+    }
+}
+
+impl std::error::Error for SpecError {}
 
-            lexicons.push(lexicon);
+/// A value in a lexicon document along with its path, for errors.
+struct Node<'a> {
+    value: &'a Value,
+    path: String,
+}
+
+impl<'a> Node<'a> {
+    fn new(value: &'a Value, path: String) -> Self {
+        Self { value, path }
+    }
+
+    fn error(&self, message: impl Into<String>) -> SpecError {
+        SpecError {
+            path: self.path.clone(),
+            message: message.into(),
         }
+    }
 
-        lexicons
+    fn child(&self, name: &str) -> Option<Node<'a>> {
+        let value = self.value.get(name)?;
+        Some(Node::new(value, format!("{}.{name}", self.path)))
+    }
+
+    fn require(&self, name: &str) -> Result<Node<'a>, SpecError> {
+        self.child(name)
+            .ok_or_else(|| self.error(format!("missing `{name}`")))
+    }
+
+    /// Deserializes an optional field holding plain data.
+    fn get<T: DeserializeOwned>(&self, name: &str) -> Result<Option<T>, SpecError> {
+        let Some(child) = self.child(name) else {
+            return Ok(None);
+        };
+
+        serde_json::from_value(child.value.clone())
+            .map(Some)
+            .map_err(|e| child.error(e.to_string()))
+    }
+
+    fn get_or_default<T: DeserializeOwned + Default>(&self, name: &str) -> Result<T, SpecError> {
+        Ok(self.get(name)?.unwrap_or_default())
+    }
+
+    fn typ(&self) -> Result<&'a str, SpecError> {
+        let typ = self.require("type")?;
+        typ.value
+            .as_str()
+            .ok_or_else(|| typ.error("expected a string"))
+    }
+
+    fn entries(&self) -> Result<Vec<(&'a str, Node<'a>)>, SpecError> {
+        let object = self
+            .value
+            .as_object()
+            .ok_or_else(|| self.error("expected an object"))?;
+
+        Ok(object
+            .iter()
+            .map(|(name, value)| {
+                let node = Node::new(value, format!("{}.{name}", self.path));
+                (name.as_str(), node)
+            })
+            .collect())
     }
 }
 
 // Core
 // =
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug)]
 pub struct Lexicon {
-    #[serde(default)]
     pub id: String,
-
-    pub revision: Option<i32>,
     pub description: Option<String>,
-
-    #[serde(flatten)]
     pub typ: LexiconType,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
-#[serde(tag = "type", rename_all = "camelCase")]
+/// The types a definition can have. Anything besides the primary types is a
+/// schema, like a shared object or string.
+#[derive(Clone, Debug)]
 pub enum LexiconType {
     Token,
-    Object {
-        #[serde(flatten)]
-        inner: LexiconObject,
-    },
-    Record {
-        #[serde(flatten)]
-        inner: LexiconRecord,
-    },
-    Query {
-        #[serde(flatten)]
-        inner: LexiconXrpcQueryProc,
-    },
-    Procedure {
-        #[serde(flatten)]
-        inner: LexiconXrpcQueryProc,
-    },
-    Blob,
-    Image,
-    Video,
-    Audio,
+    Record(LexiconRecord),
+    Query(LexiconXrpcQueryProc),
+    Procedure(LexiconXrpcQueryProc),
+    Subscription(LexiconSubscription),
+    Schema(LexiconSchema),
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+impl LexiconType {
+    fn parse(node: &Node) -> Result<Self, SpecError> {
+        Ok(match node.typ()? {
+            "token" => Self::Token,
+            "record" => Self::Record(LexiconRecord::parse(node)?),
+            "query" => Self::Query(LexiconXrpcQueryProc::parse(node)?),
+            "procedure" => Self::Procedure(LexiconXrpcQueryProc::parse(node)?),
+            "subscription" => Self::Subscription(LexiconSubscription::parse(node)?),
+            _ => Self::Schema(LexiconSchema::parse(node)?),
+        })
+    }
+}
+
+#[derive(Clone, Debug)]
 pub struct LexiconObject {
-    #[serde(default)]
     pub required: Vec<String>,
-    pub properties: HashMap<String, LexiconPrimitive>,
+    pub nullable: Vec<String>,
+    pub properties: BTreeMap<String, LexiconSchema>,
 }
 
-impl From<HashMap<String, LexiconPrimitive>> for LexiconObject {
-    fn from(value: HashMap<String, LexiconPrimitive>) -> Self {
-        Self {
-            required: Vec::default(),
-            properties: value,
+impl LexiconObject {
+    /// Parses an `object`, or the `params` of an XRPC method.
+    fn parse(node: &Node, typ: &str) -> Result<Self, SpecError> {
+        if node.typ()? != typ {
+            return Err(node.error(format!("expected type `{typ}`")));
         }
+
+        let mut properties = BTreeMap::new();
+        if let Some(child) = node.child("properties") {
+            for (name, property) in child.entries()? {
+                properties.insert(name.to_owned(), LexiconSchema::parse(&property)?);
+            }
+        }
+
+        Ok(Self {
+            required: node.get_or_default("required")?,
+            nullable: node.get_or_default("nullable")?,
+            properties,
+        })
     }
 }
 
 // Database
 // =
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug)]
 pub struct LexiconRecord {
-    key: Option<String>,
-    record: LexiconObject,
+    pub key: Option<String>,
+    pub record: LexiconObject,
+}
+
+impl LexiconRecord {
+    fn parse(node: &Node) -> Result<Self, SpecError> {
+        Ok(Self {
+            key: node.get("key")?,
+            record: LexiconObject::parse(&node.require("record")?, "object")?,
+        })
+    }
 }
 
 // XRPC
 // =
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug)]
 pub struct LexiconXrpcQueryProc {
-    #[serde(default)]
-    pub parameters: Option<XrpcParameters>,
+    pub parameters: Option<LexiconObject>,
     pub input: Option<XrpcBody>,
     pub output: Option<XrpcBody>,
-    #[serde(default)]
-    pub errors: Vec<XrpcError>,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
-pub struct XrpcParameters {
-    #[serde(rename = "type")]
-    pub typ: String,
-    pub properties: HashMap<String, LexiconPrimitive>,
+impl LexiconXrpcQueryProc {
+    fn parse(node: &Node) -> Result<Self, SpecError> {
+        Ok(Self {
+            parameters: parse_parameters(node)?,
+            input: node
+                .child("input")
+                .map(|it| XrpcBody::parse(&it))
+                .transpose()?,
+            output: node
+                .child("output")
+                .map(|it| XrpcBody::parse(&it))
+                .transpose()?,
+        })
+    }
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug)]
+pub struct LexiconSubscription {
+    pub parameters: Option<LexiconObject>,
+    /// Schema of the messages, which is a union.
+    pub message: Option<LexiconSchema>,
+}
+
+impl LexiconSubscription {
+    fn parse(node: &Node) -> Result<Self, SpecError> {
+        let message = match node.child("message") {
+            Some(message) => {
+                let schema = message.require("schema")?;
+                match LexiconSchema::parse(&schema)? {
+                    union @ LexiconSchema::Union(_) => Some(union),
+                    _ => return Err(schema.error("expected a union")),
+                }
+            }
+            None => None,
+        };
+
+        Ok(Self {
+            parameters: parse_parameters(node)?,
+            message,
+        })
+    }
+}
+
+fn parse_parameters(node: &Node) -> Result<Option<LexiconObject>, SpecError> {
+    let Some(parameters) = node.child("parameters") else {
+        return Ok(None);
+    };
+
+    let object = LexiconObject::parse(&parameters, "params")?;
+    for (name, property) in parameters.require("properties")?.entries()? {
+        if !object.properties[name].is_parameter() {
+            return Err(property.error("parameters can only be primitives or arrays of them"));
+        }
+    }
+
+    Ok(Some(object))
+}
+
+/// The body of an XRPC request or response. Its `encoding` is required but
+/// not kept, as only JSON bodies have a schema to generate types for.
+#[derive(Clone, Debug)]
 pub struct XrpcBody {
-    pub encoding: String,
-    pub schema: LexiconObject,
+    pub schema: Option<LexiconSchema>,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
-pub struct XrpcError {
-    pub name: String,
-    pub description: Option<String>,
+impl XrpcBody {
+    fn parse(node: &Node) -> Result<Self, SpecError> {
+        node.require("encoding")?;
+
+        let schema = match node.child("schema") {
+            Some(schema) => match LexiconSchema::parse(&schema)? {
+                schema @ (LexiconSchema::Object(_)
+                | LexiconSchema::Ref(_)
+                | LexiconSchema::Union(_)) => Some(schema),
+                _ => return Err(schema.error("expected an object, ref or union")),
+            },
+            None => None,
+        };
+
+        Ok(Self { schema })
+    }
 }
 
-// Primitives
+// Schemas
 // =
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
-#[serde(tag = "type", rename_all = "camelCase")]
-pub enum LexiconPrimitive {
+#[derive(Clone, Debug)]
+pub enum LexiconSchema {
+    Null,
     Boolean,
-    Number,
     Integer,
-    String {
-        #[serde(rename = "enum")]
-        enum_values: Option<Vec<String>>,
-    },
+    String(LexiconString),
+    Bytes,
+    CidLink,
+    Blob,
+    Array(Box<LexiconSchema>),
+    Object(LexiconObject),
+    /// A reference to another definition, as `nsid#name`, `nsid` for a main
+    /// definition or `#name` for one in the same document.
+    Ref(String),
+    Union(LexiconUnion),
+    Unknown,
+}
+
+impl LexiconSchema {
+    fn parse(node: &Node) -> Result<Self, SpecError> {
+        Ok(match node.typ()? {
+            "null" => Self::Null,
+            "boolean" => Self::Boolean,
+            "integer" => Self::Integer,
+            "string" => Self::String(LexiconString {
+                known_values: node.get_or_default("knownValues")?,
+                enum_values: node.get("enum")?,
+            }),
+            "bytes" => Self::Bytes,
+            "cid-link" => Self::CidLink,
+            "blob" => Self::Blob,
+            "array" => Self::Array(Box::new(Self::parse(&node.require("items")?)?)),
+            "object" => Self::Object(LexiconObject::parse(node, "object")?),
+            "ref" => Self::Ref(parse_ref(&node.require("ref")?)?),
+            "union" => {
+                let refs = node.require("refs")?;
+                let items = refs
+                    .value
+                    .as_array()
+                    .ok_or_else(|| refs.error("expected an array"))?;
+
+                let refs = items
+                    .iter()
+                    .enumerate()
+                    .map(|(i, it)| parse_ref(&Node::new(it, format!("{}[{i}]", refs.path))))
+                    .collect::<Result<_, _>>()?;
+
+                Self::Union(LexiconUnion {
+                    refs,
+                    closed: node.get_or_default("closed")?,
+                })
+            }
+            "unknown" => Self::Unknown,
+            typ @ ("token" | "record" | "query" | "procedure" | "subscription" | "params") => {
+                return Err(node.error(format!("`{typ}` can't be used here")))
+            }
+            typ => return Err(node.error(format!("unknown type `{typ}`"))),
+        })
+    }
+
+    fn is_parameter(&self) -> bool {
+        match self {
+            Self::Boolean | Self::Integer | Self::String(_) | Self::Unknown => true,
+            Self::Array(items) => items.is_parameter() && !matches!(**items, Self::Array(_)),
+            _ => false,
+        }
+    }
+}
+
+fn parse_ref(node: &Node) -> Result<String, SpecError> {
+    match node.value.as_str() {
+        Some(reference) if !reference.is_empty() && !reference.ends_with('#') => {
+            Ok(reference.to_owned())
+        }
+        _ => Err(node.error("expected a reference like `nsid#name`")),
+    }
 }
 
-// FIXME
-// type XrpcParameter = Value;
-// type XrpcBody = Value;
-// type XrpcError = Value;
+#[derive(Clone, Debug)]
+pub struct LexiconString {
+    /// Values the string usually has, though others are allowed.
+    pub known_values: Vec<String>,
+    /// The only values the string can have.
+    pub enum_values: Option<Vec<String>>,
+}
+
+impl LexiconString {
+    /// Whether the values of the string are listed, in `enum` or `knownValues`.
+    pub fn has_values(&self) -> bool {
+        self.enum_values.is_some() || !self.known_values.is_empty()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct LexiconUnion {
+    pub refs: Vec<String>,
+    /// Whether the union can't have other types in the future.
+    pub closed: bool,
+}
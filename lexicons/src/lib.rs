@@ -1,14 +1,229 @@
-pub fn add(left: usize, right: usize) -> usize {
-    left + right
+//! Types generated from the lexicons in `data/`, in modules following their
+//! NSIDs, e.g. `app.bsky.feed.post` is [`app::bsky::feed::post`].
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize, Serializer};
+
+include!(concat!(env!("OUT_DIR"), "/lexicons.rs"));
+
+/// A `token` definition, which is a name for a value without any data, like a
+/// moderation reason.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Token(&'static str);
+
+impl Token {
+    pub const fn new(id: &'static str) -> Self {
+        Self(id)
+    }
+
+    /// The `nsid#name` id of the token, which is how it's written in records.
+    pub fn as_str(&self) -> &'static str {
+        self.0
+    }
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.0)
+    }
+}
+
+impl Serialize for Token {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.0)
+    }
+}
+
+/// A `blob` in a record, which is a [`BlobRef`] or, in records from before
+/// blob refs had a `$type`, a [`LegacyBlobRef`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Blob {
+    Ref(BlobRef),
+    Legacy(LegacyBlobRef),
+}
+
+/// Reference to an uploaded blob, as stored in records.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "$type", rename = "blob", rename_all = "camelCase")]
+pub struct BlobRef {
+    #[serde(rename = "ref")]
+    pub link: CidLink,
+    pub mime_type: String,
+    pub size: u64,
+}
+
+/// A `cid-link`, which is written as `{"$link": cid}` in JSON.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CidLink {
+    #[serde(rename = "$link")]
+    pub link: String,
+}
+
+/// A `bytes` value, which is written as `{"$bytes": base64}` in JSON. The
+/// bytes are kept encoded.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Bytes {
+    #[serde(rename = "$bytes")]
+    pub base64: String,
+}
+
+/// Blob reference of older records, without the size.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LegacyBlobRef {
+    pub cid: String,
+    pub mime_type: String,
 }
 
 #[cfg(test)]
 mod tests {
+    use serde_json::json;
+
     use super::*;
 
     #[test]
-    fn it_works() {
-        let result = add(2, 2);
-        assert_eq!(result, 4);
+    fn uses_lexicon_names_in_json() {
+        let session = serde_json::from_value::<
+            com::atproto::server::create_session::CreateSessionOutput,
+        >(json!({
+            "accessJwt": "access",
+            "refreshJwt": "refresh",
+            "handle": "alice.bsky.social",
+            "did": "did:plc:alice",
+        }))
+        .unwrap();
+
+        assert_eq!(session.access_jwt, "access");
+        assert_eq!(session.email, None);
+        assert_eq!(
+            serde_json::to_value(&session).unwrap(),
+            json!({
+                "accessJwt": "access",
+                "refreshJwt": "refresh",
+                "handle": "alice.bsky.social",
+                "did": "did:plc:alice",
+            })
+        );
+
+        let entity = serde_json::from_value::<app::bsky::feed::post::Entity>(json!({
+            "index": { "start": 0, "end": 5 },
+            "type": "mention",
+            "value": "did:plc:alice",
+        }))
+        .unwrap();
+
+        assert_eq!(entity.r#type, "mention");
+    }
+
+    #[test]
+    fn keeps_unknown_values_of_open_enums() {
+        use app::bsky::notification::list_notifications::NotificationReason;
+        use com::atproto::moderation::defs::{ReasonType, REASON_SPAM};
+
+        let reasons = serde_json::from_value::<Vec<NotificationReason>>(json!([
+            "mention",
+            "starterpack-joined"
+        ]))
+        .unwrap();
+
+        assert_eq!(reasons, vec![
+            NotificationReason::Mention,
+            NotificationReason::Other("starterpack-joined".to_owned())
+        ]);
+        assert_eq!(
+            serde_json::to_value(&reasons).unwrap(),
+            json!(["mention", "starterpack-joined"])
+        );
+
+        let reason = serde_json::from_value::<ReasonType>(json!(REASON_SPAM)).unwrap();
+        assert_eq!(reason, ReasonType::ReasonSpam);
+        assert_eq!(reason.as_str(), REASON_SPAM.as_str());
+    }
+
+    #[test]
+    fn tags_unions_with_type() {
+        use app::bsky::feed::defs::{ThreadViewPost, ThreadViewPostParent};
+
+        let thread = serde_json::from_value::<ThreadViewPost>(json!({
+            "post": {
+                "uri": "at://did:plc:alice/app.bsky.feed.post/1",
+                "cid": "bafyrei",
+                "author": { "did": "did:plc:alice", "handle": "alice.bsky.social" },
+                "record": { "text": "hi" },
+                "indexedAt": "2023-05-13T17:46:40.000Z",
+            },
+            "parent": {
+                "$type": "app.bsky.feed.defs#notFoundPost",
+                "uri": "at://did:plc:bob/app.bsky.feed.post/1",
+                "notFound": true,
+            },
+            "replies": [{ "$type": "app.bsky.feed.defs#hiddenPost", "uri": "at://hidden" }],
+        }))
+        .unwrap();
+
+        assert!(matches!(
+            thread.parent,
+            Some(ThreadViewPostParent::NotFoundPost(_))
+        ));
+        assert!(matches!(
+            thread.replies.as_deref(),
+            Some([app::bsky::feed::defs::ThreadViewPostReplies::Unknown(_)])
+        ));
+        assert_eq!(
+            serde_json::to_value(&thread.parent).unwrap()["$type"],
+            "app.bsky.feed.defs#notFoundPost"
+        );
+    }
+
+    #[test]
+    fn parses_typed_and_legacy_blobs() {
+        use app::bsky::actor::profile::Profile;
+
+        let profile = serde_json::from_value::<Profile>(json!({
+            "avatar": {
+                "$type": "blob",
+                "ref": { "$link": "bafkrei" },
+                "mimeType": "image/png",
+                "size": 1234,
+            },
+            "banner": { "cid": "bafkrei", "mimeType": "image/jpeg" },
+        }))
+        .unwrap();
+
+        assert!(matches!(
+            profile.avatar,
+            Some(Blob::Ref(BlobRef { size: 1234, .. }))
+        ));
+        assert!(matches!(profile.banner, Some(Blob::Legacy(_))));
+    }
+
+    #[test]
+    fn parses_links_and_bytes() {
+        use com::atproto::sync::subscribe_repos::Commit;
+
+        let commit = serde_json::from_value::<Commit>(json!({
+            "seq": 1,
+            "rebase": false,
+            "tooBig": false,
+            "repo": "did:plc:alice",
+            "commit": { "$link": "bafyreicommit" },
+            "prev": null,
+            "blocks": { "$bytes": "OqJlcm9vdHM" },
+            "ops": [{
+                "action": "create",
+                "path": "app.bsky.feed.post/1",
+                "cid": { "$link": "bafyreipost" },
+            }],
+            "blobs": [{ "$link": "bafkreiblob" }],
+            "time": "2023-05-13T17:46:40.000Z",
+        }))
+        .unwrap();
+
+        assert_eq!(commit.commit.link, "bafyreicommit");
+        assert_eq!(commit.blocks.base64, "OqJlcm9vdHM");
+        assert_eq!(commit.blobs[0].link, "bafkreiblob");
+        assert_eq!(commit.prev, None);
     }
 }
@@ -1,240 +1,499 @@
-use std::fmt::Display;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
 
-use anyhow::Result;
-use async_recursion::async_recursion;
-use reqwest::{Request, Response, StatusCode};
+use reqwest::header::{HeaderValue, AUTHORIZATION};
+use reqwest::{Method, Request, RequestBuilder, Response, StatusCode};
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
-use thiserror::Error;
-use time::format_description::well_known::Iso8601;
-use time::OffsetDateTime;
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct ApiError {
-    error: String,
-    message: Option<String>,
-}
-
-impl Display for ApiError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        writeln!(
-            f,
-            "Error: {}\nMessage: {}",
-            self.error,
-            self.message.clone().unwrap_or_default()
-        )
-    }
+use tokio::sync::Mutex;
+use tracing::{event, Level};
+
+mod blob;
+mod embed;
+mod error;
+mod paginate;
+mod post;
+pub mod record;
+mod repo;
+mod retry;
+mod richtext;
+mod session;
+
+pub use blob::{sniff_image_mime, Blob, BlobRef, CidLink, LegacyBlobRef, MAX_IMAGE_SIZE};
+pub use embed::{
+    Embed, External, ExternalLink, Image, Images, Media, RecordEmbed, RecordWithMedia, MAX_IMAGES,
+};
+pub use error::{ApiError, ApiErrorKind, XrpcError, XrpcResult};
+use futures::Stream;
+pub use lexicons::app::bsky::actor::defs::ProfileView;
+use lexicons::app::bsky::feed::get_author_feed::GetAuthorFeedParams;
+use lexicons::app::bsky::feed::get_likes::GetLikesOutput;
+pub use lexicons::app::bsky::feed::get_likes::{GetLikesParams, Like};
+pub use lexicons::app::bsky::feed::get_post_thread::GetPostThreadParams;
+use lexicons::app::bsky::feed::get_reposted_by::GetRepostedByOutput;
+pub use lexicons::app::bsky::feed::get_reposted_by::GetRepostedByParams;
+pub use lexicons::app::bsky::feed::get_timeline::GetTimelineParams;
+use lexicons::app::bsky::graph::get_followers::{GetFollowersOutput, GetFollowersParams};
+use lexicons::app::bsky::graph::get_follows::{GetFollowsOutput, GetFollowsParams};
+pub use lexicons::app::bsky::notification::list_notifications::{
+    ListNotificationsParams, NotificationReason,
+};
+use lexicons::com::atproto::identity::resolve_handle::{ResolveHandleOutput, ResolveHandleParams};
+pub use lexicons::com::atproto::repo::list_records::ListRecordsParams;
+use lexicons::com::atproto::server::create_session::{CreateSessionInput, CreateSessionOutput};
+use lexicons::com::atproto::server::get_session::GetSessionOutput;
+use lexicons::com::atproto::server::refresh_session::RefreshSessionOutput;
+pub use paginate::{Page, PageOptions};
+pub use post::NewPost;
+pub use record::Record;
+pub use repo::{CreateRecord, DeleteRecord, GetRecord, GetRecordParams, PutRecord};
+use retry::RateLimit;
+pub use retry::RetryPolicy;
+pub use richtext::{ByteSlice, Facet, FacetFeature, RichText};
+pub use session::{FileSessionStore, SessionStore};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct XrpcAuth {
+    access_jwt: String,
+    refresh_jwt: String,
+    did: String,
 }
 
-#[derive(Debug, Error)]
-pub enum XrpcError {
-    #[error("XRPC API Error\n{0}")]
-    API(ApiError),
-    #[error("Rate limited")]
-    RateLimited,
-    #[error("Internal XRPC Client Error '{0}'")]
-    Internal(&'static str),
+/// Client for talking to an XRPC provider. The client is cheap to clone and
+/// every clone shares the same connection pool and session, so it can be
+/// handed out to as many tasks as needed.
+#[derive(Debug, Clone)]
+pub struct XrpcClient {
+    inner: Arc<XrpcClientInner>,
 }
 
-type XrpcResult<T> = Result<T, XrpcError>;
-
 #[derive(Debug)]
-pub struct XrpcAuth {
-    access_token: String,
-    refresh_token: String,
-    did: String,
+struct XrpcClientInner {
+    provider: String,
+    http: reqwest::Client,
+    auth: RwLock<Option<XrpcAuth>>,
+    // Held while refreshing the session so that concurrent requests that all
+    // hit an expired token only cause a single refresh.
+    refresh_lock: Mutex<()>,
+    session_store: Option<Arc<dyn SessionStore>>,
+    retry_policy: RetryPolicy,
 }
 
 #[derive(Debug)]
-pub struct XrpcClient {
+pub struct XrpcClientBuilder {
     provider: String,
-    http: reqwest::Client,
-    auth: Option<XrpcAuth>,
+    session_store: Option<Arc<dyn SessionStore>>,
+    retry_policy: RetryPolicy,
+    timeout: Option<Duration>,
+}
+
+impl XrpcClientBuilder {
+    /// Sets the store used to persist the session between restarts.
+    pub fn session_store(mut self, store: impl SessionStore + 'static) -> Self {
+        self.session_store = Some(Arc::new(store));
+        self
+    }
+
+    /// Sets the policy for retrying failed requests.
+    pub fn retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry_policy = policy;
+        self
+    }
+
+    /// Sets how long a request may take, from connecting until the response
+    /// body is read. Requests don't time out by default.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn build(self) -> XrpcClient {
+        let mut http = reqwest::Client::builder();
+        if let Some(timeout) = self.timeout {
+            http = http.timeout(timeout);
+        }
+
+        XrpcClient {
+            inner: Arc::new(XrpcClientInner {
+                provider: self.provider,
+                http: http.build().expect("HTTP client should be created"),
+                auth: RwLock::new(None),
+                refresh_lock: Mutex::new(()),
+                session_store: self.session_store,
+                retry_policy: self.retry_policy,
+            }),
+        }
+    }
 }
 
 impl XrpcClient {
     fn xrpc(&self, method: &str) -> String {
-        format!("{}/xrpc/{}", self.provider, method)
+        format!("{}/xrpc/{}", self.inner.provider, method)
+    }
+
+    fn auth(&self) -> Option<XrpcAuth> {
+        self.inner
+            .auth
+            .read()
+            .expect("Auth lock should not be poisoned")
+            .clone()
+    }
+
+    /// DID of the logged in account.
+    pub fn did(&self) -> Option<String> {
+        self.auth().map(|it| it.did)
+    }
+
+    fn set_auth(&self, auth: Option<XrpcAuth>) {
+        *self
+            .inner
+            .auth
+            .write()
+            .expect("Auth lock should not be poisoned") = auth;
+    }
+
+    /// Sets the current session and saves it to the session store, if any.
+    fn store_auth(&self, auth: XrpcAuth) {
+        if let Some(store) = &self.inner.session_store {
+            if let Err(e) = store.save(&auth) {
+                event!(Level::WARN, "Failed to save session: {}", e);
+            }
+        }
+
+        self.set_auth(Some(auth));
     }
 
     pub async fn new(provider: impl Into<String>) -> Self {
-        Self {
+        Self::builder(provider).build()
+    }
+
+    /// Builder for a client with a session store or retry policy.
+    pub fn builder(provider: impl Into<String>) -> XrpcClientBuilder {
+        XrpcClientBuilder {
             provider: provider.into(),
-            http: reqwest::Client::new(),
-            auth: None,
+            session_store: None,
+            retry_policy: RetryPolicy::none(),
+            timeout: None,
         }
     }
 
-    pub(crate) async fn query<I, O>(&mut self, method: &str, input: Option<I>) -> XrpcResult<O>
+    pub(crate) async fn query<I, O>(&self, method: &str, input: Option<I>) -> XrpcResult<O>
     where
         I: Serialize,
         O: DeserializeOwned,
     {
         let url = self.xrpc(method);
-        let mut builder = self.http.get(url);
+        let mut builder = self.inner.http.get(url);
 
         if let Some(input) = input {
             builder = builder.query(&input);
         }
 
-        if let Some(auth) = &self.auth {
-            builder = builder.bearer_auth(&auth.access_token);
+        self.send_query(method, builder).await
+    }
+
+    /// Queries a single page of a paginated query.
+    pub(crate) async fn query_page<I, O>(
+        &self,
+        method: &str,
+        input: &I,
+        limit: Option<u32>,
+        cursor: Option<&str>,
+    ) -> XrpcResult<O>
+    where
+        I: Serialize,
+        O: DeserializeOwned,
+    {
+        let url = self.xrpc(method);
+        let mut builder = self.inner.http.get(url).query(input);
+
+        if let Some(limit) = limit {
+            builder = builder.query(&[("limit", limit)]);
         }
 
-        let request = builder
-            .build()
-            .map_err(|_| XrpcError::Internal("Failed to build query request"))?;
+        if let Some(cursor) = cursor {
+            builder = builder.query(&[("cursor", cursor)]);
+        }
 
-        let response = self
-            .make_request(request, true)
-            .await?
-            .json::<O>()
-            .await
-            .map_err(|_| XrpcError::Internal("Failed to get query response"))?;
+        self.send_query(method, builder).await
+    }
 
-        Ok(response)
+    async fn send_query<O>(&self, method: &str, builder: RequestBuilder) -> XrpcResult<O>
+    where
+        O: DeserializeOwned,
+    {
+        let request = builder.build().map_err(|e| XrpcError::http(method, e))?;
+        let response = self.make_request(method, request).await?;
+
+        read_json(method, response).await
     }
 
-    pub(crate) async fn procedure<I>(
-        &mut self,
-        method: &str,
-        input: Option<I>,
-    ) -> XrpcResult<Response>
+    pub(crate) async fn procedure<I>(&self, method: &str, input: Option<I>) -> XrpcResult<Response>
     where
         I: Serialize,
     {
         let url = self.xrpc(method);
-        let mut builder = self.http.post(url);
+        let mut builder = self.inner.http.post(url);
 
         if let Some(input) = input {
             builder = builder.json(&input);
         }
 
-        if let Some(auth) = &self.auth {
-            builder = builder.bearer_auth(&auth.access_token);
-        }
-
-        let request = builder
-            .build()
-            .map_err(|_| XrpcError::Internal("Failed to build procedure request"))?;
-
-        let response = self.make_request(request, true).await?;
+        let request = builder.build().map_err(|e| XrpcError::http(method, e))?;
+        let response = self.make_request(method, request).await?;
 
         Ok(response)
     }
 
-    pub(crate) async fn procedure_io<I, O>(
-        &mut self,
-        method: &str,
-        input: Option<I>,
-    ) -> XrpcResult<O>
+    pub(crate) async fn procedure_io<I, O>(&self, method: &str, input: Option<I>) -> XrpcResult<O>
     where
         I: Serialize,
         O: DeserializeOwned,
     {
-        let response = self
-            .procedure(method, input)
-            .await?
-            .json::<O>()
-            .await
-            .map_err(|_| XrpcError::Internal("Failed to get procedure response"))?;
+        let response = self.procedure(method, input).await?;
 
-        Ok(response)
+        read_json(method, response).await
     }
 
-    #[async_recursion(?Send)]
-    async fn make_request(&mut self, request: Request, retry: bool) -> XrpcResult<Response> {
-        let response = self
-            .http
-            .execute(request.try_clone().expect("Request should be clonable"))
-            .await
-            .map_err(|_| XrpcError::Internal("Failed to execute request"))?;
+    async fn make_request(&self, method: &str, request: Request) -> XrpcResult<Response> {
+        let policy = &self.inner.retry_policy;
+        // Procedures might have been applied when the server failed or timed out.
+        let may_repeat = request.method() == Method::GET || policy.retry_procedures;
+        let mut refreshed = false;
+        let mut attempt = 0;
+
+        loop {
+            attempt += 1;
+
+            // The access token is attached on every attempt so a retry after a
+            // refresh picks up the new token.
+            let mut next = request.try_clone().expect("Request should be clonable");
+            let access_jwt = self.auth().map(|it| it.access_jwt);
+
+            if let Some(token) = &access_jwt {
+                let value = HeaderValue::from_str(&format!("Bearer {token}"))?;
+                next.headers_mut().insert(AUTHORIZATION, value);
+            }
+
+            let response = match self.inner.http.execute(next).await {
+                Ok(response) => response,
+                Err(e) => {
+                    let retryable = e.is_connect() || (e.is_timeout() && may_repeat);
+                    match policy.delay(attempt, None) {
+                        Some(delay) if retryable && policy.retry_connection_errors => {
+                            event!(Level::WARN, "Request failed, retrying in {delay:?}: {e}");
+                            tokio::time::sleep(delay).await;
+                            continue;
+                        }
+                        _ => return Err(XrpcError::http(method, e)),
+                    }
+                }
+            };
+
+            let status = response.status();
+            if status == StatusCode::OK {
+                return Ok(response);
+            }
+
+            if status == StatusCode::TOO_MANY_REQUESTS {
+                let limit = RateLimit::from_headers(response.headers());
+                match policy.delay(attempt, limit.retry_after) {
+                    Some(delay) if policy.retry_rate_limited => {
+                        event!(Level::WARN, "Rate limited, retrying in {delay:?}");
+                        tokio::time::sleep(delay).await;
+                        continue;
+                    }
+                    _ => {
+                        return Err(XrpcError::RateLimited {
+                            nsid: method.to_owned(),
+                            retry_after: limit.retry_after,
+                            remaining: limit.remaining,
+                        })
+                    }
+                }
+            }
 
-        // If the response failed we find out the reason
-        if response.status() != StatusCode::OK {
-            let error = response
-                .json::<ApiError>()
+            if status.is_server_error() && policy.retry_server_errors && may_repeat {
+                if let Some(delay) = policy.delay(attempt, None) {
+                    event!(
+                        Level::WARN,
+                        "Server responded with {status}, retrying in {delay:?}"
+                    );
+                    tokio::time::sleep(delay).await;
+                    continue;
+                }
+            }
+
+            // If the response failed we find out the reason
+            let body = response
+                .bytes()
                 .await
-                .map_err(|_| XrpcError::Internal("Failed to parse api error"))?;
+                .map_err(|e| XrpcError::http(method, e))?;
+            let error = XrpcError::from_response(method, status, &body);
+
+            // Return early if the error isn't an expired token, we already refreshed or if
+            // the client isn't authenticated to begin with.
+            let Some(access_jwt) = access_jwt else {
+                return Err(error);
+            };
 
-            // Return early if the error isn't an expired token, value of retry is false or
-            // if the client isn't authenticated to begin with.
-            if "ExpiredToken" != &error.error || !retry || self.auth.is_none() {
-                return Err(XrpcError::API(error));
+            if error.api_error() != Some(&ApiErrorKind::ExpiredToken) || refreshed {
+                return Err(error);
             }
 
-            self.refresh_auth().await?;
-            return self.make_request(request, false).await;
+            self.refresh_expired(&access_jwt).await?;
+            refreshed = true;
         }
-
-        Ok(response)
     }
 
+    /// Logs into the provider. When a session store is configured the saved
+    /// session is resumed if it is still valid, and a new session is only
+    /// created with the password when it can't be refreshed anymore.
     pub async fn login(
-        &mut self,
+        &self,
         handle: impl Into<String>,
         password: impl Into<String>,
     ) -> XrpcResult<()> {
-        let body = CreateSessionRequest {
-            identifier: handle.into(),
+        let handle = handle.into();
+
+        if self.resume_session(&handle).await? {
+            return Ok(());
+        }
+
+        let body = CreateSessionInput {
+            identifier: handle,
             password: password.into(),
         };
 
         let session = self
-            .procedure_io::<_, SessionResponse>("com.atproto.server.createSession", Some(body))
+            .procedure_io::<_, CreateSessionOutput>("com.atproto.server.createSession", Some(body))
             .await?;
 
-        self.auth = Some(XrpcAuth {
-            access_token: session.access_jwt,
-            refresh_token: session.refresh_jwt,
+        self.store_auth(XrpcAuth {
+            access_jwt: session.access_jwt,
+            refresh_jwt: session.refresh_jwt,
             did: session.did,
         });
 
         Ok(())
     }
 
-    pub async fn refresh_auth(&mut self) -> XrpcResult<()> {
-        let Some(auth) = &self.auth else {
+    /// Attempts to resume the session saved in the session store, returning
+    /// whether it succeeded. Sessions that are no longer accepted by the
+    /// provider are discarded.
+    async fn resume_session(&self, handle: &str) -> XrpcResult<bool> {
+        let Some(store) = &self.inner.session_store else {
+            return Ok(false);
+        };
+
+        let saved = match store.load() {
+            Ok(Some(saved)) => saved,
+            Ok(None) => return Ok(false),
+            Err(e) => {
+                event!(Level::WARN, "Failed to load saved session: {}", e);
+                return Ok(false);
+            }
+        };
+
+        self.set_auth(Some(saved));
+
+        // Validating the session, this refreshes the tokens if the access token
+        // expired while we were offline.
+        let session = match self
+            .query::<(), GetSessionOutput>("com.atproto.server.getSession", None)
+            .await
+        {
+            Ok(session) => session,
+            Err(e) if e.is_auth_error() => {
+                event!(Level::INFO, "Saved session is no longer valid: {}", e);
+                self.discard_session();
+                return Ok(false);
+            }
+            Err(e) => {
+                self.set_auth(None);
+                return Err(e);
+            }
+        };
+
+        let handle = handle.trim_start_matches('@');
+        if !session.handle.eq_ignore_ascii_case(handle) && session.did != handle {
+            event!(
+                Level::INFO,
+                "Saved session belongs to '{}', not resuming it",
+                session.handle
+            );
+            self.discard_session();
+            return Ok(false);
+        }
+
+        Ok(true)
+    }
+
+    fn discard_session(&self) {
+        self.set_auth(None);
+
+        if let Some(store) = &self.inner.session_store {
+            if let Err(e) = store.clear() {
+                event!(Level::WARN, "Failed to clear saved session: {}", e);
+            }
+        }
+    }
+
+    pub async fn refresh_auth(&self) -> XrpcResult<()> {
+        let _guard = self.inner.refresh_lock.lock().await;
+        self.refresh_session().await
+    }
+
+    /// Refreshes the session after `expired` was rejected, unless another task
+    /// already refreshed it while we were waiting for the lock.
+    async fn refresh_expired(&self, expired: &str) -> XrpcResult<()> {
+        let _guard = self.inner.refresh_lock.lock().await;
+
+        match self.auth() {
+            Some(auth) if auth.access_jwt == expired => self.refresh_session().await,
+            _ => Ok(()),
+        }
+    }
+
+    async fn refresh_session(&self) -> XrpcResult<()> {
+        let Some(auth) = self.auth() else {
             return Ok(());
         };
 
-        let url = self.xrpc("com.atproto.server.refreshSession");
+        let method = "com.atproto.server.refreshSession";
         let response = self
+            .inner
             .http
-            .post(url)
-            .bearer_auth(&auth.refresh_token)
+            .post(self.xrpc(method))
+            .bearer_auth(&auth.refresh_jwt)
             .send()
             .await
-            .map_err(|_| XrpcError::Internal("Failed to build session refresh request"))?;
+            .map_err(|e| XrpcError::http(method, e))?;
 
-        if response.status() != StatusCode::OK {
-            let error = response
-                .json::<ApiError>()
+        let status = response.status();
+        if status != StatusCode::OK {
+            let body = response
+                .bytes()
                 .await
-                .map_err(|_| XrpcError::Internal("Failed to parse api error"))?;
+                .map_err(|e| XrpcError::http(method, e))?;
 
-            return Err(XrpcError::API(error));
+            return Err(XrpcError::from_response(method, status, &body));
         }
 
-        let response = response
-            .json::<SessionResponse>()
-            .await
-            .map_err(|_| XrpcError::Internal("Failed to get session refresh response"))?;
+        let response = read_json::<RefreshSessionOutput>(method, response).await?;
 
-        self.auth = Some(XrpcAuth {
-            access_token: response.access_jwt,
-            refresh_token: response.refresh_jwt,
+        self.store_auth(XrpcAuth {
+            access_jwt: response.access_jwt,
+            refresh_jwt: response.refresh_jwt,
             did: response.did,
         });
 
         Ok(())
     }
 
-    pub async fn get_post_thread(
-        &mut self,
-        input: GetPostThreadParams,
-    ) -> XrpcResult<GetPostThread> {
+    pub async fn get_post_thread(&self, input: GetPostThreadParams) -> XrpcResult<GetPostThread> {
         let post_thread = self
             .query("app.bsky.feed.getPostThread", Some(input))
             .await?;
@@ -242,62 +501,161 @@ impl XrpcClient {
         Ok(post_thread)
     }
 
-    pub async fn post_reply(
-        &mut self,
-        parent_uri: impl Into<String>,
-        parent_cid: impl Into<String>,
-        contents: impl Into<String>,
-    ) -> XrpcResult<String> {
-        let Some(auth) = &self.auth else {
-            return Err(XrpcError::Internal("Endpoint requires authentication"));
-        };
-
-        let parent_uri = parent_uri.into();
-        let parent_cid = parent_cid.into();
-
-        let now = OffsetDateTime::now_utc()
-            .format(&Iso8601::DEFAULT)
-            .map_err(|_| XrpcError::Internal("Failed creating datetime"))?;
-
-        let input = json!({
-            "collection": "app.bsky.feed.post",
-            "repo": auth.did,
-            "record": {
-                "$type": "app.bsky.feed.post",
-                "createdAt": now,
-                "reply": {
-                    "parent": {
-                        "uri": parent_uri.clone(),
-                        "cid": parent_cid.clone(),
-                    },
-                    "root": {
-                        "uri": parent_uri,
-                        "cid": parent_cid,
-                    }
-                },
-                "text": contents.into(),
-            }
-        });
-
-        let response = self
-            .procedure_io::<_, Value>("com.atproto.repo.createRecord", Some(input))
+    pub async fn resolve_handle(&self, handle: &str) -> XrpcResult<String> {
+        let response: ResolveHandleOutput = self
+            .query(
+                "com.atproto.identity.resolveHandle",
+                Some(ResolveHandleParams {
+                    handle: Some(handle.to_owned()),
+                }),
+            )
             .await?;
 
-        Ok(response
-            .get("uri")
-            .ok_or(XrpcError::Internal("Could not get uri"))?
-            .to_string())
+        Ok(response.did)
     }
 
-    pub async fn list_notifications(&mut self) -> XrpcResult<ListNotifications> {
+    /// Lists a single page of notifications, newest first.
+    pub async fn list_notifications(
+        &self,
+        params: ListNotificationsParams,
+    ) -> XrpcResult<ListNotifications> {
         let notifications = self
-            .query::<(), _>("app.bsky.notification.listNotifications", None)
+            .query("app.bsky.notification.listNotifications", Some(params))
             .await?;
 
         Ok(notifications)
     }
 
-    pub async fn seen_notifications(&mut self, moment: String) -> XrpcResult<()> {
+    /// Streams notifications, newest first. The limit and cursor of `params`
+    /// are ignored, as they are set while paginating.
+    pub fn notifications(
+        &self,
+        params: ListNotificationsParams,
+        options: PageOptions,
+    ) -> impl Stream<Item = XrpcResult<Notification>> + Send + 'static {
+        let params = ListNotificationsParams {
+            limit: None,
+            cursor: None,
+            ..params
+        };
+
+        self.paginate::<_, ListNotifications>(
+            "app.bsky.notification.listNotifications",
+            params,
+            options,
+        )
+    }
+
+    /// Streams the followers of `actor`.
+    pub fn followers(
+        &self,
+        actor: impl Into<String>,
+        options: PageOptions,
+    ) -> impl Stream<Item = XrpcResult<ProfileView>> + Send + 'static {
+        let params = GetFollowersParams {
+            actor: actor.into(),
+            limit: None,
+            cursor: None,
+        };
+
+        self.paginate::<_, GetFollowersOutput>("app.bsky.graph.getFollowers", params, options)
+    }
+
+    /// Streams the accounts followed by `actor`.
+    pub fn follows(
+        &self,
+        actor: impl Into<String>,
+        options: PageOptions,
+    ) -> impl Stream<Item = XrpcResult<ProfileView>> + Send + 'static {
+        let params = GetFollowsParams {
+            actor: actor.into(),
+            limit: None,
+            cursor: None,
+        };
+
+        self.paginate::<_, GetFollowsOutput>("app.bsky.graph.getFollows", params, options)
+    }
+
+    /// Streams the posts and reposts of `actor`, newest first.
+    pub fn author_feed(
+        &self,
+        actor: impl Into<String>,
+        options: PageOptions,
+    ) -> impl Stream<Item = XrpcResult<FeedViewPost>> + Send + 'static {
+        let params = GetAuthorFeedParams {
+            actor: actor.into(),
+            limit: None,
+            cursor: None,
+        };
+
+        self.paginate::<_, Feed>("app.bsky.feed.getAuthorFeed", params, options)
+    }
+
+    /// Streams the home timeline of the logged in account. The limit and
+    /// cursor of `params` are ignored, as they are set while paginating.
+    pub fn timeline(
+        &self,
+        params: GetTimelineParams,
+        options: PageOptions,
+    ) -> impl Stream<Item = XrpcResult<FeedViewPost>> + Send + 'static {
+        let params = GetTimelineParams {
+            limit: None,
+            cursor: None,
+            ..params
+        };
+
+        self.paginate::<_, Feed>("app.bsky.feed.getTimeline", params, options)
+    }
+
+    /// Streams the likes of a post. The limit and cursor of `params` are
+    /// ignored, as they are set while paginating.
+    pub fn likes(
+        &self,
+        params: GetLikesParams,
+        options: PageOptions,
+    ) -> impl Stream<Item = XrpcResult<Like>> + Send + 'static {
+        let params = GetLikesParams {
+            limit: None,
+            cursor: None,
+            ..params
+        };
+
+        self.paginate::<_, GetLikesOutput>("app.bsky.feed.getLikes", params, options)
+    }
+
+    /// Streams the accounts that reposted a post. The limit and cursor of
+    /// `params` are ignored, as they are set while paginating.
+    pub fn reposted_by(
+        &self,
+        params: GetRepostedByParams,
+        options: PageOptions,
+    ) -> impl Stream<Item = XrpcResult<ProfileView>> + Send + 'static {
+        let params = GetRepostedByParams {
+            limit: None,
+            cursor: None,
+            ..params
+        };
+
+        self.paginate::<_, GetRepostedByOutput>("app.bsky.feed.getRepostedBy", params, options)
+    }
+
+    /// Streams the records of a collection in a repository. The limit and
+    /// cursor of `params` are ignored, as they are set while paginating.
+    pub fn records(
+        &self,
+        params: ListRecordsParams,
+        options: PageOptions,
+    ) -> impl Stream<Item = XrpcResult<RecordEntry>> + Send + 'static {
+        let params = ListRecordsParams {
+            limit: None,
+            cursor: None,
+            ..params
+        };
+
+        self.paginate::<_, ListRecords>("com.atproto.repo.listRecords", params, options)
+    }
+
+    pub async fn seen_notifications(&self, moment: String) -> XrpcResult<()> {
         let input = json!({ "seenAt": moment });
 
         self.procedure::<_>("app.bsky.notification.updateSeen", Some(input))
@@ -307,73 +665,261 @@ impl XrpcClient {
     }
 }
 
-// TODO: vvvv USE AUTOMATIC LEXICON GENERATION IN FUUUUUTURE vvvv
+/// Reads the body of a successful response as JSON.
+async fn read_json<O: DeserializeOwned>(method: &str, response: Response) -> XrpcResult<O> {
+    let status = response.status();
+    let body = response
+        .bytes()
+        .await
+        .map_err(|e| XrpcError::http(method, e))?;
 
-// Auth
+    serde_json::from_slice(&body).map_err(|e| XrpcError::decode(method, status, &body, e))
+}
+
+// TODO: The views below hold records, which the generated lexicon types only
+// have as untyped JSON. Switch them to the generated types once records can be
+// typed as `Record` there. `StrongRef` and `ReplyRef` are kept for their
+// methods.
+
+// Post Thread
 // =
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
-pub struct ResolveHandleResponse {
-    pub did: String,
+pub struct GetPostThread {
+    pub thread: ThreadView,
+}
+
+/// A post in a thread, or the reason it can't be shown. Views of other types
+/// are kept as [`ThreadView::Unknown`].
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "$type")]
+pub enum ThreadView {
+    #[serde(rename = "app.bsky.feed.defs#threadViewPost")]
+    Post(Box<ThreadViewPost>),
+    #[serde(rename = "app.bsky.feed.defs#notFoundPost")]
+    NotFound(NotFoundPost),
+    #[serde(rename = "app.bsky.feed.defs#blockedPost")]
+    Blocked(BlockedPost),
+    #[serde(untagged)]
+    Unknown(Value),
 }
 
-#[derive(Serialize)]
+impl ThreadView {
+    pub fn post(&self) -> Option<&PostView> {
+        match self {
+            Self::Post(thread) => Some(&thread.post),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
-pub struct CreateSessionRequest {
-    pub identifier: String,
-    pub password: String,
+pub struct ThreadViewPost {
+    pub post: PostView,
+    pub parent: Option<Box<ThreadView>>,
+    #[serde(default)]
+    pub replies: Vec<ThreadView>,
 }
 
+/// A post which was deleted, or never existed.
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
-pub struct SessionResponse {
-    pub access_jwt: String,
-    pub refresh_jwt: String,
-    pub handle: String,
+pub struct NotFoundPost {
+    pub uri: String,
+    pub not_found: bool,
+}
+
+/// A post hidden because of a block between its author and the viewer.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockedPost {
+    pub uri: String,
+    pub blocked: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PostView {
+    pub uri: String,
+    pub cid: String,
+    pub author: PostAuthor,
+    pub record: Record,
+}
+
+impl PostView {
+    pub fn strong_ref(&self) -> StrongRef {
+        StrongRef {
+            uri: self.uri.clone(),
+            cid: self.cid.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PostAuthor {
     pub did: String,
-    pub email: Option<String>,
+    pub handle: String,
+    pub display_name: Option<String>,
 }
 
-// Post Thread
+/// `app.bsky.feed.post#replyRef`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReplyRef {
+    pub root: StrongRef,
+    pub parent: StrongRef,
+}
+
+impl ReplyRef {
+    /// Reply to `post`, in the thread `post` belongs to.
+    pub fn to(post: &PostView) -> Self {
+        let parent = post.strong_ref();
+        let root = match post.record.as_post().and_then(|it| it.reply.as_ref()) {
+            Some(reply) => reply.root.clone(),
+            None => parent.clone(),
+        };
+
+        Self { root, parent }
+    }
+}
+
+// Graph
+// =
+
+impl Page for GetFollowersOutput {
+    type Item = ProfileView;
+
+    fn cursor(&self) -> Option<&str> {
+        self.cursor.as_deref()
+    }
+
+    fn into_items(self) -> Vec<Self::Item> {
+        self.followers
+    }
+}
+
+impl Page for GetFollowsOutput {
+    type Item = ProfileView;
+
+    fn cursor(&self) -> Option<&str> {
+        self.cursor.as_deref()
+    }
+
+    fn into_items(self) -> Vec<Self::Item> {
+        self.follows
+    }
+}
+
+// Feeds
 // =
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
-pub struct GetPostThreadParams {
-    pub uri: String,
-    pub depth: Option<i32>,
+pub struct Feed {
+    pub cursor: Option<String>,
+    pub feed: Vec<FeedViewPost>,
+}
+
+impl Page for Feed {
+    type Item = FeedViewPost;
+
+    fn cursor(&self) -> Option<&str> {
+        self.cursor.as_deref()
+    }
+
+    fn into_items(self) -> Vec<Self::Item> {
+        self.feed
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
-pub struct GetPostThread {
-    pub thread: ThreadView,
+pub struct FeedViewPost {
+    pub post: PostView,
+    pub reply: Option<FeedReplyRef>,
+    pub reason: Option<Value>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
-pub struct ThreadView {
-    pub post: Option<PostView>,
-    pub parent: Option<Box<ThreadView>>,
-    #[serde(default)]
-    pub replies: Vec<ThreadView>,
+pub struct FeedReplyRef {
+    pub root: PostView,
+    pub parent: PostView,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+impl Page for GetLikesOutput {
+    type Item = Like;
+
+    fn cursor(&self) -> Option<&str> {
+        self.cursor.as_deref()
+    }
+
+    fn into_items(self) -> Vec<Self::Item> {
+        self.likes
+    }
+}
+
+impl Page for GetRepostedByOutput {
+    type Item = ProfileView;
+
+    fn cursor(&self) -> Option<&str> {
+        self.cursor.as_deref()
+    }
+
+    fn into_items(self) -> Vec<Self::Item> {
+        self.reposted_by
+    }
+}
+
+// Repository
+// =
+
+/// `com.atproto.repo.strongRef`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
-pub struct PostView {
+pub struct StrongRef {
     pub uri: String,
     pub cid: String,
-    pub author: PostAuthor,
-    pub record: Record,
+}
+
+impl StrongRef {
+    /// Key of the record, the last segment of its `at://` URI.
+    pub fn rkey(&self) -> Option<&str> {
+        let path = self.uri.strip_prefix("at://")?;
+        let (_, rkey) = path.rsplit_once('/')?;
+
+        Some(rkey).filter(|it| !it.is_empty())
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
-pub struct PostAuthor {
-    pub did: String,
-    pub handle: String,
+pub struct ListRecords {
+    pub cursor: Option<String>,
+    pub records: Vec<RecordEntry>,
+}
+
+impl Page for ListRecords {
+    type Item = RecordEntry;
+
+    fn cursor(&self) -> Option<&str> {
+        self.cursor.as_deref()
+    }
+
+    fn into_items(self) -> Vec<Self::Item> {
+        self.records
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecordEntry {
+    pub uri: String,
+    pub cid: String,
+    pub value: Record,
 }
 
 // Notifications
@@ -382,37 +928,135 @@ pub struct PostAuthor {
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ListNotifications {
+    pub cursor: Option<String>,
     pub notifications: Vec<Notification>,
 }
 
+impl Page for ListNotifications {
+    type Item = Notification;
+
+    fn cursor(&self) -> Option<&str> {
+        self.cursor.as_deref()
+    }
+
+    fn into_items(self) -> Vec<Self::Item> {
+        self.notifications
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Notification {
     pub uri: String,
     pub cid: String,
-    pub author: Value,
-    #[serde(flatten)]
+    pub author: ProfileView,
     pub reason: NotificationReason,
     pub record: Record,
     pub is_read: bool,
+    pub indexed_at: String,
 }
 
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
-#[serde(tag = "reason", rename_all = "camelCase")]
-pub enum NotificationReason {
-    Like,
-    Repost,
-    Follow,
-    Mention,
-    Reply,
-    Quote,
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn post(uri: &str, reply: Option<ReplyRef>) -> PostView {
+        PostView {
+            uri: uri.to_owned(),
+            cid: format!("cid-{uri}"),
+            author: PostAuthor {
+                did: "did:plc:alice".to_owned(),
+                handle: "alice.bsky.social".to_owned(),
+                display_name: None,
+            },
+            record: Record::Post(Box::new(record::Post {
+                text: "hi".to_owned(),
+                facets: Vec::new(),
+                reply,
+                embed: None,
+                langs: Vec::new(),
+                created_at: "2023-05-13T17:46:40.000Z".to_owned(),
+            })),
+        }
+    }
 
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub struct Record {
-    // FIXME: Contents of record depends on type, make this an enum
-    pub text: Option<String>,
-    #[serde(rename = "$type")]
-    pub typ: String,
+    #[test]
+    fn replies_keep_the_thread_root() {
+        let root = post("at://root", None);
+        assert_eq!(ReplyRef::to(&root).root, root.strong_ref());
+
+        let reply = post("at://reply", Some(ReplyRef::to(&root)));
+        let reply_ref = ReplyRef::to(&reply);
+        assert_eq!(reply_ref.root, root.strong_ref());
+        assert_eq!(reply_ref.parent, reply.strong_ref());
+    }
+
+    #[test]
+    fn keeps_unknown_thread_views() {
+        let thread = json!({
+            "thread": {
+                "$type": "app.bsky.feed.defs#threadViewPost",
+                "post": {
+                    "uri": "at://reply",
+                    "cid": "bafyreply",
+                    "author": { "did": "did:plc:alice", "handle": "alice.bsky.social" },
+                    "record": {
+                        "$type": "app.bsky.feed.post",
+                        "text": "hi",
+                        "embed": { "$type": "app.bsky.embed.video", "video": {} },
+                        "createdAt": "2023-05-13T17:46:40.000Z",
+                    },
+                },
+                "parent": { "$type": "app.bsky.feed.defs#mutedPost", "uri": "at://parent" },
+            },
+        });
+
+        let thread = serde_json::from_value::<GetPostThread>(thread)
+            .unwrap()
+            .thread;
+        let ThreadView::Post(thread) = thread else {
+            panic!("expected a post, got {thread:?}");
+        };
+        assert_eq!(thread.post.record.text(), Some("hi"));
+        assert!(matches!(
+            thread.parent.as_deref(),
+            Some(ThreadView::Unknown(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn retries_timed_out_queries_but_not_procedures() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        // Accepts connections without ever responding
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let provider = format!("http://{}", listener.local_addr().unwrap());
+        let connections = Arc::new(AtomicUsize::new(0));
+        let counter = connections.clone();
+        tokio::spawn(async move {
+            let mut sockets = Vec::new();
+            while let Ok((socket, _)) = listener.accept().await {
+                counter.fetch_add(1, Ordering::SeqCst);
+                sockets.push(socket);
+            }
+        });
+
+        let mut policy = RetryPolicy::exponential(2);
+        policy.base_delay = Duration::from_millis(1);
+        let client = XrpcClient::builder(provider)
+            .retry_policy(policy)
+            .timeout(Duration::from_millis(100))
+            .build();
+
+        let error = client.resolve_handle("alice.test").await.unwrap_err();
+        assert!(matches!(error, XrpcError::Http { ref source, .. } if source.is_timeout()));
+        assert_eq!(connections.load(Ordering::SeqCst), 2);
+
+        let error = client
+            .procedure("com.example.write", Some(json!({})))
+            .await
+            .unwrap_err();
+        assert!(matches!(error, XrpcError::Http { ref source, .. } if source.is_timeout()));
+        assert_eq!(connections.load(Ordering::SeqCst), 3);
+    }
 }
@@ -0,0 +1,103 @@
+pub use lexicons::{Blob, BlobRef, CidLink, LegacyBlobRef};
+use reqwest::header::CONTENT_TYPE;
+use serde::Deserialize;
+
+use super::{read_json, Image, XrpcClient, XrpcError, XrpcResult};
+
+/// Largest image accepted by `app.bsky.embed.images`, in bytes.
+pub const MAX_IMAGE_SIZE: usize = 1_000_000;
+
+#[derive(Debug, Deserialize)]
+struct UploadBlob {
+    blob: BlobRef,
+}
+
+/// Detects the MIME type of an image from its first bytes, for the image
+/// formats Bluesky supports.
+pub fn sniff_image_mime(bytes: &[u8]) -> Option<&'static str> {
+    match bytes {
+        [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A, ..] => Some("image/png"),
+        [0xFF, 0xD8, 0xFF, ..] => Some("image/jpeg"),
+        [b'G', b'I', b'F', b'8', b'7' | b'9', b'a', ..] => Some("image/gif"),
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => Some("image/webp"),
+        _ => None,
+    }
+}
+
+impl XrpcClient {
+    /// Uploads a blob, which has to be referenced by a record soon after or
+    /// it's deleted.
+    pub async fn upload_blob(&self, bytes: Vec<u8>, mime: &str) -> XrpcResult<BlobRef> {
+        let method = "com.atproto.repo.uploadBlob";
+        let request = self
+            .inner
+            .http
+            .post(self.xrpc(method))
+            .header(CONTENT_TYPE, mime)
+            .body(bytes)
+            .build()
+            .map_err(|e| XrpcError::http(method, e))?;
+
+        let response = self.make_request(method, request).await?;
+        let output = read_json::<UploadBlob>(method, response).await?;
+
+        Ok(output.blob)
+    }
+
+    /// Uploads an image, checking its type and size first.
+    pub async fn upload_image_blob(&self, bytes: Vec<u8>) -> XrpcResult<BlobRef> {
+        let mime = sniff_image_mime(&bytes).ok_or(XrpcError::UnsupportedImage)?;
+
+        if bytes.len() > MAX_IMAGE_SIZE {
+            return Err(XrpcError::BlobTooLarge {
+                size: bytes.len(),
+                max: MAX_IMAGE_SIZE,
+            });
+        }
+
+        self.upload_blob(bytes, mime).await
+    }
+
+    /// Uploads an image for an images embed.
+    pub async fn upload_image(&self, bytes: Vec<u8>, alt: impl Into<String>) -> XrpcResult<Image> {
+        Ok(Image {
+            image: self.upload_image_blob(bytes).await?,
+            alt: alt.into(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn sniffs_image_types() {
+        assert_eq!(
+            sniff_image_mime(b"\x89PNG\r\n\x1a\n...."),
+            Some("image/png")
+        );
+        assert_eq!(sniff_image_mime(b"\xff\xd8\xff\xe0"), Some("image/jpeg"));
+        assert_eq!(
+            sniff_image_mime(b"RIFF\0\0\0\0WEBPVP8 "),
+            Some("image/webp")
+        );
+        assert_eq!(sniff_image_mime(b"<svg></svg>"), None);
+    }
+
+    #[test]
+    fn blob_refs_have_a_type() {
+        let blob = json!({
+            "$type": "blob",
+            "ref": { "$link": "bafkrei" },
+            "mimeType": "image/png",
+            "size": 1234,
+        });
+
+        let parsed = serde_json::from_value::<BlobRef>(blob.clone()).unwrap();
+        assert_eq!(parsed.link.link, "bafkrei");
+        assert_eq!(serde_json::to_value(parsed).unwrap(), blob);
+    }
+}
@@ -0,0 +1,247 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::{BlobRef, StrongRef, XrpcError, XrpcResult};
+
+/// Most images in an `app.bsky.embed.images`.
+pub const MAX_IMAGES: usize = 4;
+
+/// Media or content embedded in a post. Embeds of other types, or which don't
+/// match their lexicon like images with legacy blob refs, are kept as
+/// [`Embed::Unknown`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "$type")]
+pub enum Embed {
+    #[serde(rename = "app.bsky.embed.images")]
+    Images(Images),
+    #[serde(rename = "app.bsky.embed.external")]
+    External(External),
+    #[serde(rename = "app.bsky.embed.record")]
+    Record(RecordEmbed),
+    #[serde(rename = "app.bsky.embed.recordWithMedia")]
+    RecordWithMedia(RecordWithMedia),
+    #[serde(untagged)]
+    Unknown(Value),
+}
+
+impl Embed {
+    /// Embeds up to [`MAX_IMAGES`] images, uploaded with
+    /// [`XrpcClient::upload_image`](super::XrpcClient::upload_image).
+    pub fn images(images: Vec<Image>) -> XrpcResult<Self> {
+        Media::images(images).map(Self::from)
+    }
+
+    /// Embeds a link card.
+    pub fn external(link: ExternalLink) -> Self {
+        Self::External(External { external: link })
+    }
+
+    /// Embeds another record, which quotes it when it's a post.
+    pub fn record(record: StrongRef) -> Self {
+        Self::Record(RecordEmbed { record })
+    }
+
+    /// Embeds another record along with images or a link card.
+    pub fn record_with_media(record: StrongRef, media: Media) -> Self {
+        Self::RecordWithMedia(RecordWithMedia {
+            record: RecordEmbed { record },
+            media,
+        })
+    }
+}
+
+impl From<Media> for Embed {
+    fn from(media: Media) -> Self {
+        match media {
+            Media::Images(images) => Self::Images(images),
+            Media::External(external) => Self::External(external),
+            Media::Unknown(value) => Self::Unknown(value),
+        }
+    }
+}
+
+/// Embeds which can be combined with a record in
+/// `app.bsky.embed.recordWithMedia`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "$type")]
+pub enum Media {
+    #[serde(rename = "app.bsky.embed.images")]
+    Images(Images),
+    #[serde(rename = "app.bsky.embed.external")]
+    External(External),
+    #[serde(untagged)]
+    Unknown(Value),
+}
+
+impl Media {
+    /// Up to [`MAX_IMAGES`] images.
+    pub fn images(images: Vec<Image>) -> XrpcResult<Self> {
+        if images.len() > MAX_IMAGES {
+            return Err(XrpcError::TooManyImages {
+                count: images.len(),
+                max: MAX_IMAGES,
+            });
+        }
+
+        Ok(Self::Images(Images { images }))
+    }
+
+    pub fn external(link: ExternalLink) -> Self {
+        Self::External(External { external: link })
+    }
+}
+
+/// `app.bsky.embed.images`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Images {
+    pub images: Vec<Image>,
+}
+
+/// `app.bsky.embed.images#image`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Image {
+    pub image: BlobRef,
+    /// Description of the image for screen readers.
+    pub alt: String,
+}
+
+/// `app.bsky.embed.external`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct External {
+    pub external: ExternalLink,
+}
+
+/// `app.bsky.embed.external#external`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExternalLink {
+    pub uri: String,
+    pub title: String,
+    pub description: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thumb: Option<BlobRef>,
+}
+
+impl ExternalLink {
+    pub fn new(
+        uri: impl Into<String>,
+        title: impl Into<String>,
+        description: impl Into<String>,
+    ) -> Self {
+        Self {
+            uri: uri.into(),
+            title: title.into(),
+            description: description.into(),
+            thumb: None,
+        }
+    }
+
+    /// Sets the thumbnail, uploaded with
+    /// [`XrpcClient::upload_image_blob`](super::XrpcClient::upload_image_blob).
+    pub fn thumb(mut self, thumb: BlobRef) -> Self {
+        self.thumb = Some(thumb);
+        self
+    }
+}
+
+/// `app.bsky.embed.record`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecordEmbed {
+    pub record: StrongRef,
+}
+
+/// `app.bsky.embed.recordWithMedia`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecordWithMedia {
+    pub record: RecordEmbed,
+    pub media: Media,
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::atp::CidLink;
+
+    fn image(alt: &str) -> Image {
+        Image {
+            image: BlobRef {
+                link: CidLink {
+                    link: "bafkrei".to_owned(),
+                },
+                mime_type: "image/png".to_owned(),
+                size: 1234,
+            },
+            alt: alt.to_owned(),
+        }
+    }
+
+    #[test]
+    fn embeds_at_most_four_images() {
+        let images = (0..5).map(|i| image(&i.to_string())).collect::<Vec<_>>();
+
+        assert!(Embed::images(images[..4].to_vec()).is_ok());
+        assert!(matches!(
+            Embed::images(images),
+            Err(XrpcError::TooManyImages { count: 5, max: 4 })
+        ));
+    }
+
+    #[test]
+    fn serializes_images_with_type() {
+        let embed = Embed::images(vec![image("A cat")]).unwrap();
+
+        assert_eq!(
+            serde_json::to_value(embed).unwrap(),
+            json!({
+                "$type": "app.bsky.embed.images",
+                "images": [{
+                    "image": {
+                        "$type": "blob",
+                        "ref": { "$link": "bafkrei" },
+                        "mimeType": "image/png",
+                        "size": 1234,
+                    },
+                    "alt": "A cat",
+                }],
+            })
+        );
+    }
+
+    #[test]
+    fn serializes_quotes_with_media() {
+        let record = StrongRef {
+            uri: "at://did:plc:alice/app.bsky.feed.post/1".to_owned(),
+            cid: "bafyrei".to_owned(),
+        };
+        let link = ExternalLink::new("https://example.com", "Example", "");
+        let embed = Embed::record_with_media(record, Media::external(link));
+
+        assert_eq!(
+            serde_json::to_value(embed).unwrap(),
+            json!({
+                "$type": "app.bsky.embed.recordWithMedia",
+                "record": {
+                    "record": {
+                        "uri": "at://did:plc:alice/app.bsky.feed.post/1",
+                        "cid": "bafyrei",
+                    },
+                },
+                "media": {
+                    "$type": "app.bsky.embed.external",
+                    "external": {
+                        "uri": "https://example.com",
+                        "title": "Example",
+                        "description": "",
+                    },
+                },
+            })
+        );
+    }
+}
@@ -0,0 +1,294 @@
+use std::fmt::Display;
+use std::time::Duration;
+
+use reqwest::header::InvalidHeaderValue;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// Maximum number of bytes of a response body kept in an error.
+const MAX_ERROR_BODY_LENGTH: usize = 512;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiError {
+    pub error: ApiErrorKind,
+    pub message: Option<String>,
+}
+
+impl Display for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.message {
+            Some(message) => write!(f, "{}: {}", self.error, message),
+            None => write!(f, "{}", self.error),
+        }
+    }
+}
+
+/// The `error` name of an XRPC error response. Errors that are shared by many
+/// methods or that the client acts upon have their own variant.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum ApiErrorKind {
+    InvalidRequest,
+    AuthenticationRequired,
+    ExpiredToken,
+    InvalidToken,
+    AccountTakedown,
+    NotFound,
+    RateLimitExceeded,
+    InternalServerError,
+    /// A compare-and-swap write found a different record or commit.
+    InvalidSwap,
+    Other(String),
+}
+
+impl ApiErrorKind {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::InvalidRequest => "InvalidRequest",
+            Self::AuthenticationRequired => "AuthenticationRequired",
+            Self::ExpiredToken => "ExpiredToken",
+            Self::InvalidToken => "InvalidToken",
+            Self::AccountTakedown => "AccountTakedown",
+            Self::NotFound => "NotFound",
+            Self::RateLimitExceeded => "RateLimitExceeded",
+            Self::InternalServerError => "InternalServerError",
+            Self::InvalidSwap => "InvalidSwap",
+            Self::Other(name) => name,
+        }
+    }
+}
+
+impl From<String> for ApiErrorKind {
+    fn from(value: String) -> Self {
+        match value.as_str() {
+            "InvalidRequest" => Self::InvalidRequest,
+            "AuthenticationRequired" => Self::AuthenticationRequired,
+            "ExpiredToken" => Self::ExpiredToken,
+            "InvalidToken" => Self::InvalidToken,
+            "AccountTakedown" => Self::AccountTakedown,
+            "NotFound" => Self::NotFound,
+            "RateLimitExceeded" => Self::RateLimitExceeded,
+            "InternalServerError" => Self::InternalServerError,
+            "InvalidSwap" => Self::InvalidSwap,
+            _ => Self::Other(value),
+        }
+    }
+}
+
+impl From<ApiErrorKind> for String {
+    fn from(value: ApiErrorKind) -> Self {
+        match value {
+            ApiErrorKind::Other(name) => name,
+            kind => kind.as_str().to_owned(),
+        }
+    }
+}
+
+impl Display for ApiErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum XrpcError {
+    #[error("XRPC call to {nsid} failed with {status}: {error}")]
+    Api {
+        nsid: String,
+        status: StatusCode,
+        error: ApiError,
+    },
+    #[error("XRPC call to {nsid} failed with {status}: {body}")]
+    Status {
+        nsid: String,
+        status: StatusCode,
+        body: String,
+    },
+    #[error("XRPC call to {nsid} was rate limited, retry after {retry_after:?}")]
+    RateLimited {
+        nsid: String,
+        retry_after: Option<Duration>,
+        remaining: Option<u32>,
+    },
+    #[error("XRPC call to {nsid} requires authentication")]
+    Unauthenticated { nsid: String },
+    #[error("HTTP request for {nsid} failed")]
+    Http {
+        nsid: String,
+        #[source]
+        source: reqwest::Error,
+    },
+    #[error("Failed to decode {status} response of {nsid}: {body}")]
+    Decode {
+        nsid: String,
+        status: StatusCode,
+        body: String,
+        #[source]
+        source: serde_json::Error,
+    },
+    #[error("Response of {nsid} is missing '{field}'")]
+    MissingField { nsid: String, field: &'static str },
+    #[error("Blob of {size} bytes is larger than the limit of {max} bytes")]
+    BlobTooLarge { size: usize, max: usize },
+    #[error("Blob is not a supported image")]
+    UnsupportedImage,
+    #[error("Posts can have at most {max} images, got {count}")]
+    TooManyImages { count: usize, max: usize },
+    #[error("Invalid header value")]
+    InvalidHeader(#[from] InvalidHeaderValue),
+    #[error("Failed to format timestamp")]
+    Time(#[from] time::error::Format),
+}
+
+impl XrpcError {
+    /// The kind of error returned by the XRPC API, if this is an API error.
+    pub fn api_error(&self) -> Option<&ApiErrorKind> {
+        match self {
+            Self::Api { error, .. } => Some(&error.error),
+            _ => None,
+        }
+    }
+
+    /// Whether this error means the session is no longer usable.
+    pub fn is_auth_error(&self) -> bool {
+        match self {
+            Self::Unauthenticated { .. } => true,
+            Self::Api { status, .. } if *status == StatusCode::UNAUTHORIZED => true,
+            _ => matches!(
+                self.api_error(),
+                Some(
+                    ApiErrorKind::AuthenticationRequired
+                        | ApiErrorKind::ExpiredToken
+                        | ApiErrorKind::InvalidToken
+                        | ApiErrorKind::AccountTakedown
+                )
+            ),
+        }
+    }
+
+    pub(crate) fn http(nsid: &str, source: reqwest::Error) -> Self {
+        Self::Http {
+            nsid: nsid.to_owned(),
+            source,
+        }
+    }
+
+    pub(crate) fn decode(
+        nsid: &str,
+        status: StatusCode,
+        body: &[u8],
+        source: serde_json::Error,
+    ) -> Self {
+        Self::Decode {
+            nsid: nsid.to_owned(),
+            status,
+            body: truncate_body(body),
+            source,
+        }
+    }
+
+    /// Creates the error for an unsuccessful response, keeping the XRPC error
+    /// if the body contains one. A 401 without one means the call needs a
+    /// session.
+    pub(crate) fn from_response(nsid: &str, status: StatusCode, body: &[u8]) -> Self {
+        match serde_json::from_slice::<ApiError>(body) {
+            Ok(error) => Self::Api {
+                nsid: nsid.to_owned(),
+                status,
+                error,
+            },
+            Err(_) if status == StatusCode::UNAUTHORIZED => Self::Unauthenticated {
+                nsid: nsid.to_owned(),
+            },
+            Err(_) => Self::Status {
+                nsid: nsid.to_owned(),
+                status,
+                body: truncate_body(body),
+            },
+        }
+    }
+}
+
+pub type XrpcResult<T> = Result<T, XrpcError>;
+
+fn truncate_body(body: &[u8]) -> String {
+    let body = String::from_utf8_lossy(body);
+    if body.len() <= MAX_ERROR_BODY_LENGTH {
+        return body.into_owned();
+    }
+
+    let mut end = MAX_ERROR_BODY_LENGTH;
+    while !body.is_char_boundary(end) {
+        end -= 1;
+    }
+
+    format!("{}...", &body[..end])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NSID: &str = "app.bsky.feed.getTimeline";
+
+    #[test]
+    fn maps_api_error_names() {
+        let body = br#"{"error": "ExpiredToken", "message": "Token has expired"}"#;
+        let error = XrpcError::from_response(NSID, StatusCode::BAD_REQUEST, body);
+
+        assert_eq!(error.api_error(), Some(&ApiErrorKind::ExpiredToken));
+        assert!(error.is_auth_error());
+        assert_eq!(
+            error.to_string(),
+            "XRPC call to app.bsky.feed.getTimeline failed with 400 Bad Request: ExpiredToken: \
+             Token has expired"
+        );
+
+        let body = br#"{"error": "BlockedActor"}"#;
+        let error = XrpcError::from_response(NSID, StatusCode::BAD_REQUEST, body);
+
+        assert_eq!(
+            error.api_error(),
+            Some(&ApiErrorKind::Other("BlockedActor".to_owned()))
+        );
+        assert!(!error.is_auth_error());
+        assert_eq!(
+            serde_json::to_value(ApiErrorKind::Other("BlockedActor".to_owned())).unwrap(),
+            "BlockedActor"
+        );
+    }
+
+    #[test]
+    fn maps_unauthorized_without_api_error() {
+        let error = XrpcError::from_response(NSID, StatusCode::UNAUTHORIZED, b"Unauthorized");
+        assert!(matches!(error, XrpcError::Unauthenticated { .. }));
+        assert!(error.is_auth_error());
+
+        // An XRPC error is kept, as the client acts on expired tokens.
+        let body = br#"{"error": "ExpiredToken"}"#;
+        let error = XrpcError::from_response(NSID, StatusCode::UNAUTHORIZED, body);
+        assert_eq!(error.api_error(), Some(&ApiErrorKind::ExpiredToken));
+
+        // Any error with a 401 means the session is unusable.
+        let body = br#"{"error": "AuthMissing"}"#;
+        let error = XrpcError::from_response(NSID, StatusCode::UNAUTHORIZED, body);
+        assert!(error.is_auth_error());
+
+        let error = XrpcError::from_response(NSID, StatusCode::BAD_GATEWAY, b"<html>");
+        assert!(matches!(error, XrpcError::Status { body, .. } if body == "<html>"));
+    }
+
+    #[test]
+    fn truncates_bodies_on_char_boundaries() {
+        let short = "a".repeat(MAX_ERROR_BODY_LENGTH);
+        assert_eq!(truncate_body(short.as_bytes()), short);
+
+        // "é" is two bytes, so the cut at 512 falls in the middle of one.
+        let body = format!("a{}", "é".repeat(300));
+        let truncated = truncate_body(body.as_bytes());
+
+        assert_eq!(truncated.len(), 511 + "...".len());
+        assert!(truncated.ends_with("é..."));
+    }
+}
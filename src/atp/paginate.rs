@@ -0,0 +1,193 @@
+use futures::stream::{self, Stream, TryStreamExt};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use super::{XrpcClient, XrpcError, XrpcResult};
+
+/// Largest `limit` accepted by the paginated queries.
+const MAX_PAGE_SIZE: u32 = 100;
+
+/// Output of a query which returns its results in pages linked by a cursor.
+pub trait Page: DeserializeOwned {
+    type Item;
+
+    /// The cursor for the next page, `None` on the last page.
+    fn cursor(&self) -> Option<&str>;
+
+    fn into_items(self) -> Vec<Self::Item>;
+}
+
+/// Controls how many items are requested per page and in total while
+/// paginating.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PageOptions {
+    /// Number of items to request per page. The server default is used when
+    /// unset.
+    pub page_size: Option<u32>,
+    /// Stop after this many items have been yielded.
+    pub max_items: Option<usize>,
+}
+
+impl PageOptions {
+    pub fn page_size(mut self, page_size: u32) -> Self {
+        self.page_size = Some(page_size);
+        self
+    }
+
+    pub fn max_items(mut self, max_items: usize) -> Self {
+        self.max_items = Some(max_items);
+        self
+    }
+
+    /// The limit to request for the next page, given the number of items
+    /// that were already yielded.
+    fn limit(&self, yielded: usize) -> Option<u32> {
+        let remaining = self
+            .max_items
+            .map(|max| max.saturating_sub(yielded).min(u32::MAX as usize) as u32);
+
+        let limit = match (self.page_size, remaining) {
+            (Some(size), Some(remaining)) => Some(size.min(remaining)),
+            (size, remaining) => size.or(remaining),
+        };
+
+        limit.map(|it| it.min(MAX_PAGE_SIZE))
+    }
+}
+
+struct PageState<P> {
+    client: XrpcClient,
+    params: P,
+    progress: Progress,
+}
+
+/// How far pagination got, updated after every page.
+#[derive(Debug, Default)]
+struct Progress {
+    cursor: Option<String>,
+    yielded: usize,
+    done: bool,
+}
+
+impl Progress {
+    /// Moves past a page with the next `cursor`, returning the items of the
+    /// page that are within `options.max_items`.
+    fn advance<T>(
+        &mut self,
+        options: &PageOptions,
+        cursor: Option<String>,
+        mut items: Vec<T>,
+    ) -> Vec<T> {
+        // Stopping when the cursor doesn't move to not loop forever on a
+        // misbehaving server.
+        self.done = cursor.is_none() || cursor == self.cursor || items.is_empty();
+        self.cursor = cursor;
+
+        if let Some(max) = options.max_items {
+            items.truncate(max.saturating_sub(self.yielded));
+        }
+
+        self.yielded += items.len();
+        items
+    }
+}
+
+impl XrpcClient {
+    /// Queries `method` page by page, yielding the items of every page until
+    /// the last page or `options.max_items` is reached. The `limit` and
+    /// `cursor` parameters are added to `params` by the paginator.
+    pub fn paginate<P, O>(
+        &self,
+        method: &'static str,
+        params: P,
+        options: PageOptions,
+    ) -> impl Stream<Item = XrpcResult<O::Item>> + Send + 'static
+    where
+        P: Serialize + Send + Sync + 'static,
+        O: Page + Send + 'static,
+        O::Item: Send + 'static,
+    {
+        let state = PageState {
+            client: self.clone(),
+            params,
+            progress: Progress::default(),
+        };
+
+        let pages = stream::try_unfold(state, move |mut state| async move {
+            let progress = &state.progress;
+            let limit = options.limit(progress.yielded);
+            if progress.done || limit == Some(0) {
+                return Ok(None);
+            }
+
+            let page = state
+                .client
+                .query_page::<_, O>(method, &state.params, limit, progress.cursor.as_deref())
+                .await?;
+
+            let cursor = page.cursor().map(str::to_owned);
+            let items = state.progress.advance(&options, cursor, page.into_items());
+
+            Ok::<_, XrpcError>(Some((items, state)))
+        });
+
+        pages
+            .map_ok(|items| stream::iter(items.into_iter().map(Ok)))
+            .try_flatten()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limits_page_size_to_remaining_items() {
+        let options = PageOptions::default().page_size(50).max_items(120);
+
+        assert_eq!(options.limit(0), Some(50));
+        assert_eq!(options.limit(100), Some(20));
+        assert_eq!(options.limit(120), Some(0));
+        assert_eq!(PageOptions::default().limit(10), None);
+        assert_eq!(PageOptions::default().max_items(30).limit(10), Some(20));
+        assert_eq!(PageOptions::default().max_items(500).limit(0), Some(100));
+    }
+
+    #[test]
+    fn stops_when_the_cursor_repeats() {
+        let options = PageOptions::default();
+        let mut progress = Progress::default();
+
+        progress.advance(&options, Some("a".to_owned()), vec![1, 2]);
+        assert!(!progress.done);
+
+        assert_eq!(
+            progress.advance(&options, Some("a".to_owned()), vec![3]),
+            vec![3]
+        );
+        assert!(progress.done);
+    }
+
+    #[test]
+    fn stops_at_an_empty_page() {
+        let mut progress = Progress::default();
+
+        let items = progress.advance::<i32>(&PageOptions::default(), Some("a".to_owned()), vec![]);
+        assert!(items.is_empty());
+        assert!(progress.done);
+    }
+
+    #[test]
+    fn truncates_the_page_reaching_max_items() {
+        let options = PageOptions::default().max_items(3);
+        let mut progress = Progress::default();
+
+        progress.advance(&options, Some("a".to_owned()), vec![1, 2]);
+        assert_eq!(
+            progress.advance(&options, Some("b".to_owned()), vec![3, 4]),
+            vec![3]
+        );
+        assert_eq!(progress.yielded, 3);
+        assert_eq!(options.limit(progress.yielded), Some(0));
+    }
+}
@@ -0,0 +1,77 @@
+use time::format_description::well_known::Iso8601;
+use time::OffsetDateTime;
+
+use super::record::Post;
+use super::{
+    CreateRecord, Embed, Record, ReplyRef, RichText, StrongRef, XrpcClient, XrpcError, XrpcResult,
+};
+
+/// A post to create with [`XrpcClient::create_post`].
+#[derive(Debug, Clone, Default)]
+pub struct NewPost {
+    pub text: String,
+    pub reply: Option<ReplyRef>,
+    pub embed: Option<Embed>,
+    pub langs: Vec<String>,
+}
+
+impl NewPost {
+    pub fn new(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Makes the post a reply, use [`ReplyRef::to`] to reply to a post in its
+    /// thread.
+    pub fn reply(mut self, reply: ReplyRef) -> Self {
+        self.reply = Some(reply);
+        self
+    }
+
+    pub fn embed(mut self, embed: Embed) -> Self {
+        self.embed = Some(embed);
+        self
+    }
+
+    /// Adds a language of the text, as a BCP-47 tag.
+    pub fn lang(mut self, lang: impl Into<String>) -> Self {
+        self.langs.push(lang.into());
+        self
+    }
+}
+
+impl XrpcClient {
+    /// Creates a post. Mentions, links and tags in the text are turned into
+    /// facets.
+    pub async fn create_post(&self, post: NewPost) -> XrpcResult<StrongRef> {
+        let Some(did) = self.did() else {
+            return Err(XrpcError::Unauthenticated {
+                nsid: "com.atproto.repo.createRecord".to_owned(),
+            });
+        };
+
+        let text = RichText::detect(self, post.text).await;
+        let record = Record::Post(Box::new(Post {
+            text: text.text,
+            facets: text.facets,
+            reply: post.reply,
+            embed: post.embed,
+            langs: post.langs,
+            created_at: OffsetDateTime::now_utc().format(&Iso8601::DEFAULT)?,
+        }));
+
+        self.create_record(CreateRecord::new(did, "app.bsky.feed.post", record))
+            .await
+    }
+
+    /// Posts a reply, use [`ReplyRef::to`] to reply to a post in its thread.
+    pub async fn post_reply(
+        &self,
+        reply: ReplyRef,
+        contents: impl Into<String>,
+    ) -> XrpcResult<StrongRef> {
+        self.create_post(NewPost::new(contents).reply(reply)).await
+    }
+}
@@ -0,0 +1,213 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::{Blob, Embed, Facet, ReplyRef, StrongRef};
+
+/// A record in a repository, tagged by its `$type`. Records of other types,
+/// or which don't match their lexicon, are kept as [`Record::Unknown`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "$type")]
+pub enum Record {
+    #[serde(rename = "app.bsky.feed.post")]
+    Post(Box<Post>),
+    #[serde(rename = "app.bsky.feed.like")]
+    Like(Like),
+    #[serde(rename = "app.bsky.feed.repost")]
+    Repost(Repost),
+    #[serde(rename = "app.bsky.graph.follow")]
+    Follow(Follow),
+    #[serde(rename = "app.bsky.graph.block")]
+    Block(Block),
+    #[serde(rename = "app.bsky.actor.profile")]
+    Profile(Profile),
+    #[serde(untagged)]
+    Unknown(Value),
+}
+
+impl Record {
+    pub fn as_post(&self) -> Option<&Post> {
+        match self {
+            Self::Post(post) => Some(post),
+            _ => None,
+        }
+    }
+
+    /// Text of the record if it's a post.
+    pub fn text(&self) -> Option<&str> {
+        self.as_post().map(|it| it.text.as_str())
+    }
+}
+
+/// `app.bsky.feed.post`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Post {
+    pub text: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub facets: Vec<Facet>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reply: Option<ReplyRef>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub embed: Option<Embed>,
+    /// Languages of the text, as BCP-47 tags.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub langs: Vec<String>,
+    pub created_at: String,
+}
+
+/// `app.bsky.feed.like`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Like {
+    pub subject: StrongRef,
+    pub created_at: String,
+}
+
+/// `app.bsky.feed.repost`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Repost {
+    pub subject: StrongRef,
+    pub created_at: String,
+}
+
+/// `app.bsky.graph.follow`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Follow {
+    /// DID of the followed account.
+    pub subject: String,
+    pub created_at: String,
+}
+
+/// `app.bsky.graph.block`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Block {
+    /// DID of the blocked account.
+    pub subject: String,
+    pub created_at: String,
+}
+
+/// `app.bsky.actor.profile`
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Profile {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub avatar: Option<Blob>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub banner: Option<Blob>,
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::atp::PostView;
+
+    #[test]
+    fn parses_records_by_type() {
+        let post = json!({
+            "$type": "app.bsky.feed.post",
+            "text": "Hello",
+            "langs": ["en"],
+            "createdAt": "2023-05-13T17:46:40.000Z",
+        });
+
+        let record = serde_json::from_value::<Record>(post.clone()).unwrap();
+        assert_eq!(record.text(), Some("Hello"));
+        assert_eq!(serde_json::to_value(&record).unwrap(), post);
+
+        let like = json!({
+            "$type": "app.bsky.feed.like",
+            "subject": { "uri": "at://did:plc:alice/app.bsky.feed.post/1", "cid": "bafyrei" },
+            "createdAt": "2023-05-13T17:46:40.000Z",
+        });
+        assert!(matches!(
+            serde_json::from_value(like).unwrap(),
+            Record::Like(_)
+        ));
+    }
+
+    #[test]
+    fn keeps_unknown_records() {
+        let unknown = json!({ "$type": "app.example.record", "value": 1 });
+        let record = serde_json::from_value::<Record>(unknown.clone()).unwrap();
+
+        assert_eq!(record, Record::Unknown(unknown.clone()));
+        assert_eq!(serde_json::to_value(&record).unwrap(), unknown);
+
+        // Posts missing required fields can't be typed either.
+        let invalid = json!({ "$type": "app.bsky.feed.post" });
+        assert!(matches!(
+            serde_json::from_value(invalid).unwrap(),
+            Record::Unknown(_)
+        ));
+    }
+
+    #[test]
+    fn keeps_posts_with_unknown_embeds_and_facets() {
+        let root = json!({ "uri": "at://did:plc:alice/app.bsky.feed.post/1", "cid": "bafyroot" });
+        let post = json!({
+            "uri": "at://did:plc:bob/app.bsky.feed.post/2",
+            "cid": "bafyreply",
+            "author": { "did": "did:plc:bob", "handle": "bob.test" },
+            "record": {
+                "$type": "app.bsky.feed.post",
+                "text": "Look #new",
+                "facets": [{
+                    "index": { "byteStart": 5, "byteEnd": 9 },
+                    "features": [{ "$type": "app.example.facet#new" }],
+                }],
+                "reply": { "root": root, "parent": root },
+                "embed": { "$type": "app.bsky.embed.video", "video": {} },
+                "createdAt": "2023-05-13T17:46:40.000Z",
+            },
+        });
+
+        let post = serde_json::from_value::<PostView>(post).unwrap();
+        assert_eq!(post.record.text(), Some("Look #new"));
+        assert!(matches!(
+            post.record.as_post().unwrap().embed,
+            Some(Embed::Unknown(_))
+        ));
+        assert_eq!(
+            ReplyRef::to(&post).root.uri,
+            "at://did:plc:alice/app.bsky.feed.post/1"
+        );
+
+        // Images from before blob refs had a `$type`
+        let legacy = json!({
+            "$type": "app.bsky.feed.post",
+            "text": "Old",
+            "embed": {
+                "$type": "app.bsky.embed.images",
+                "images": [{ "image": { "cid": "bafkrei", "mimeType": "image/jpeg" }, "alt": "" }],
+            },
+            "createdAt": "2023-03-01T00:00:00.000Z",
+        });
+        let record = serde_json::from_value::<Record>(legacy).unwrap();
+        assert_eq!(record.text(), Some("Old"));
+    }
+
+    #[test]
+    fn parses_profiles_with_legacy_blobs() {
+        let profile = json!({
+            "$type": "app.bsky.actor.profile",
+            "displayName": "Alice",
+            "avatar": { "cid": "bafkrei", "mimeType": "image/jpeg" },
+        });
+
+        let record = serde_json::from_value::<Record>(profile.clone()).unwrap();
+        let Record::Profile(parsed) = &record else {
+            panic!("expected a profile, got {record:?}");
+        };
+        assert!(matches!(parsed.avatar, Some(Blob::Legacy(_))));
+        assert_eq!(serde_json::to_value(&record).unwrap(), profile);
+    }
+}
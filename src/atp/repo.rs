@@ -0,0 +1,231 @@
+use serde::{Deserialize, Serialize};
+
+use super::{ListRecords, ListRecordsParams, Record, StrongRef, XrpcClient, XrpcResult};
+
+/// Input of `com.atproto.repo.createRecord`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateRecord {
+    /// Handle or DID of the repository.
+    pub repo: String,
+    pub collection: String,
+    /// Key of the record, generated by the server when unset.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rkey: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub validate: Option<bool>,
+    pub record: Record,
+    /// Only create the record if the repository is at this commit.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub swap_commit: Option<String>,
+}
+
+impl CreateRecord {
+    pub fn new(repo: impl Into<String>, collection: impl Into<String>, record: Record) -> Self {
+        Self {
+            repo: repo.into(),
+            collection: collection.into(),
+            rkey: None,
+            validate: None,
+            record,
+            swap_commit: None,
+        }
+    }
+
+    pub fn rkey(mut self, rkey: impl Into<String>) -> Self {
+        self.rkey = Some(rkey.into());
+        self
+    }
+
+    pub fn swap_commit(mut self, commit: impl Into<String>) -> Self {
+        self.swap_commit = Some(commit.into());
+        self
+    }
+}
+
+/// Input of `com.atproto.repo.putRecord`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PutRecord {
+    /// Handle or DID of the repository.
+    pub repo: String,
+    pub collection: String,
+    pub rkey: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub validate: Option<bool>,
+    pub record: Record,
+    /// Only write the record if its current version has this CID.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub swap_record: Option<String>,
+    /// Only write the record if the repository is at this commit.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub swap_commit: Option<String>,
+}
+
+impl PutRecord {
+    pub fn new(
+        repo: impl Into<String>,
+        collection: impl Into<String>,
+        rkey: impl Into<String>,
+        record: Record,
+    ) -> Self {
+        Self {
+            repo: repo.into(),
+            collection: collection.into(),
+            rkey: rkey.into(),
+            validate: None,
+            record,
+            swap_record: None,
+            swap_commit: None,
+        }
+    }
+
+    pub fn swap_record(mut self, cid: impl Into<String>) -> Self {
+        self.swap_record = Some(cid.into());
+        self
+    }
+
+    pub fn swap_commit(mut self, commit: impl Into<String>) -> Self {
+        self.swap_commit = Some(commit.into());
+        self
+    }
+}
+
+/// Input of `com.atproto.repo.deleteRecord`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeleteRecord {
+    /// Handle or DID of the repository.
+    pub repo: String,
+    pub collection: String,
+    pub rkey: String,
+    /// Only delete the record if its current version has this CID.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub swap_record: Option<String>,
+    /// Only delete the record if the repository is at this commit.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub swap_commit: Option<String>,
+}
+
+impl DeleteRecord {
+    pub fn new(
+        repo: impl Into<String>,
+        collection: impl Into<String>,
+        rkey: impl Into<String>,
+    ) -> Self {
+        Self {
+            repo: repo.into(),
+            collection: collection.into(),
+            rkey: rkey.into(),
+            swap_record: None,
+            swap_commit: None,
+        }
+    }
+
+    pub fn swap_record(mut self, cid: impl Into<String>) -> Self {
+        self.swap_record = Some(cid.into());
+        self
+    }
+
+    pub fn swap_commit(mut self, commit: impl Into<String>) -> Self {
+        self.swap_commit = Some(commit.into());
+        self
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetRecordParams {
+    /// Handle or DID of the repository.
+    pub repo: String,
+    pub collection: String,
+    pub rkey: String,
+    /// Version of the record, the latest when unset.
+    pub cid: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetRecord {
+    pub uri: String,
+    pub cid: Option<String>,
+    pub value: Record,
+}
+
+impl XrpcClient {
+    pub async fn create_record(&self, input: CreateRecord) -> XrpcResult<StrongRef> {
+        self.procedure_io("com.atproto.repo.createRecord", Some(input))
+            .await
+    }
+
+    /// Creates or replaces the record with the given key.
+    pub async fn put_record(&self, input: PutRecord) -> XrpcResult<StrongRef> {
+        self.procedure_io("com.atproto.repo.putRecord", Some(input))
+            .await
+    }
+
+    pub async fn delete_record(&self, input: DeleteRecord) -> XrpcResult<()> {
+        self.procedure("com.atproto.repo.deleteRecord", Some(input))
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_record(&self, params: GetRecordParams) -> XrpcResult<GetRecord> {
+        self.query("com.atproto.repo.getRecord", Some(params)).await
+    }
+
+    /// Lists a single page of records, use [`XrpcClient::records`] to go
+    /// through all of them.
+    pub async fn list_records(&self, params: ListRecordsParams) -> XrpcResult<ListRecords> {
+        self.query("com.atproto.repo.listRecords", Some(params))
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn serializes_swaps_only_when_set() {
+        let record = Record::Unknown(json!({ "$type": "app.example.record" }));
+
+        let input = DeleteRecord::new("did:plc:bot", "app.bsky.feed.post", "3jui7kd54zh2y");
+        assert_eq!(
+            serde_json::to_value(input).unwrap(),
+            json!({
+                "repo": "did:plc:bot",
+                "collection": "app.bsky.feed.post",
+                "rkey": "3jui7kd54zh2y",
+            })
+        );
+
+        let input = PutRecord::new("did:plc:bot", "app.example.record", "self", record)
+            .swap_record("bafyrei")
+            .swap_commit("bafyrec");
+        assert_eq!(
+            serde_json::to_value(input).unwrap(),
+            json!({
+                "repo": "did:plc:bot",
+                "collection": "app.example.record",
+                "rkey": "self",
+                "record": { "$type": "app.example.record" },
+                "swapRecord": "bafyrei",
+                "swapCommit": "bafyrec",
+            })
+        );
+    }
+
+    #[test]
+    fn strong_refs_know_their_record_key() {
+        let reference = StrongRef {
+            uri: "at://did:plc:bot/app.bsky.feed.post/3jui7kd54zh2y".to_owned(),
+            cid: "bafyrei".to_owned(),
+        };
+
+        assert_eq!(reference.rkey(), Some("3jui7kd54zh2y"));
+    }
+}
@@ -0,0 +1,213 @@
+use std::time::Duration;
+
+use rand::Rng;
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use time::format_description::well_known::Rfc2822;
+use time::OffsetDateTime;
+
+/// Controls if and how failed requests are retried by the client. Retrying is
+/// opt-in, the default policy never retries.
+///
+/// Server errors and timeouts are only retried for queries by default, as a
+/// procedure may have been applied before the server failed, and retrying it
+/// would apply it twice. Requests only time out when the client has a timeout,
+/// see [`XrpcClientBuilder::timeout`](super::XrpcClientBuilder::timeout).
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Maximum number of attempts for a single request, including the first.
+    pub max_attempts: u32,
+    /// Delay before the first retry, doubled on every following retry.
+    pub base_delay: Duration,
+    /// Upper bound for the delay between two attempts. Requests that are rate
+    /// limited for longer than this are not retried.
+    pub max_delay: Duration,
+    pub retry_rate_limited: bool,
+    pub retry_server_errors: bool,
+    pub retry_connection_errors: bool,
+    /// Also retries procedures on server errors and timeouts, for procedures
+    /// which are safe to apply more than once.
+    pub retry_procedures: bool,
+}
+
+impl RetryPolicy {
+    /// Policy that never retries a request.
+    pub fn none() -> Self {
+        Self {
+            max_attempts: 1,
+            base_delay: Duration::ZERO,
+            max_delay: Duration::ZERO,
+            retry_rate_limited: false,
+            retry_server_errors: false,
+            retry_connection_errors: false,
+            retry_procedures: false,
+        }
+    }
+
+    /// Policy retrying rate limited requests, server errors and connection
+    /// errors with exponential backoff, except for server errors and timeouts
+    /// of procedures.
+    pub fn exponential(max_attempts: u32) -> Self {
+        Self {
+            max_attempts,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(60),
+            retry_rate_limited: true,
+            retry_server_errors: true,
+            retry_connection_errors: true,
+            retry_procedures: false,
+        }
+    }
+
+    /// Returns how long to wait before the next attempt after `attempt` failed,
+    /// or `None` when the request shouldn't be retried anymore. A delay
+    /// requested by the server takes precedence over the backoff.
+    pub(crate) fn delay(&self, attempt: u32, requested: Option<Duration>) -> Option<Duration> {
+        if attempt >= self.max_attempts {
+            return None;
+        }
+
+        match requested {
+            Some(delay) if delay > self.max_delay => None,
+            Some(delay) => Some(delay),
+            None => Some(self.backoff(attempt)),
+        }
+    }
+
+    /// Exponential backoff with full jitter.
+    fn backoff(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(16);
+        let ceiling = self
+            .base_delay
+            .saturating_mul(1 << exponent)
+            .min(self.max_delay);
+
+        ceiling.mul_f64(rand::thread_rng().gen_range(0.0..=1.0))
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::none()
+    }
+}
+
+/// Rate limit information sent by the server through the `RateLimit-*` and
+/// `Retry-After` headers.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct RateLimit {
+    pub retry_after: Option<Duration>,
+    pub remaining: Option<u32>,
+}
+
+impl RateLimit {
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        Self::parse(headers, OffsetDateTime::now_utc())
+    }
+
+    fn parse(headers: &HeaderMap, now: OffsetDateTime) -> Self {
+        let header = |name: &str| headers.get(name).and_then(|it| it.to_str().ok());
+
+        let remaining = header("ratelimit-remaining").and_then(|it| it.trim().parse().ok());
+
+        let retry_after = header(RETRY_AFTER.as_str())
+            .and_then(|it| parse_retry_after(it, now))
+            .or_else(|| header("ratelimit-reset").and_then(|it| parse_reset(it, now)));
+
+        Self {
+            retry_after,
+            remaining,
+        }
+    }
+}
+
+/// Parses a `Retry-After` header, which is either a number of seconds or an
+/// HTTP date.
+fn parse_retry_after(value: &str, now: OffsetDateTime) -> Option<Duration> {
+    let value = value.trim();
+
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let date = OffsetDateTime::parse(value, &Rfc2822).ok()?;
+    Some((date - now).try_into().unwrap_or(Duration::ZERO))
+}
+
+/// Parses a `RateLimit-Reset` header. The draft standard specifies a number of
+/// seconds, but Bluesky sends a unix timestamp, so large values are treated as
+/// one.
+fn parse_reset(value: &str, now: OffsetDateTime) -> Option<Duration> {
+    const TIMESTAMP_THRESHOLD: i64 = 1_000_000_000;
+
+    let value = value.trim().parse::<i64>().ok()?;
+    if value < TIMESTAMP_THRESHOLD {
+        return Some(Duration::from_secs(value.max(0) as u64));
+    }
+
+    let reset = OffsetDateTime::from_unix_timestamp(value).ok()?;
+    Some((reset - now).try_into().unwrap_or(Duration::ZERO))
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::header::HeaderValue;
+
+    use super::*;
+
+    fn headers(values: &[(&'static str, &'static str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in values {
+            headers.insert(*name, HeaderValue::from_static(value));
+        }
+        headers
+    }
+
+    #[test]
+    fn parses_retry_after_seconds() {
+        let now = OffsetDateTime::from_unix_timestamp(1_684_000_000).unwrap();
+        let limit = RateLimit::parse(
+            &headers(&[("retry-after", "30"), ("ratelimit-remaining", "0")]),
+            now,
+        );
+
+        assert_eq!(limit.retry_after, Some(Duration::from_secs(30)));
+        assert_eq!(limit.remaining, Some(0));
+    }
+
+    #[test]
+    fn parses_retry_after_date() {
+        let now = OffsetDateTime::from_unix_timestamp(1_684_000_000).unwrap();
+        let limit = RateLimit::parse(
+            &headers(&[("retry-after", "Sat, 13 May 2023 17:47:00 GMT")]),
+            now,
+        );
+
+        assert_eq!(limit.retry_after, Some(Duration::from_secs(20)));
+    }
+
+    #[test]
+    fn parses_reset_timestamp() {
+        let now = OffsetDateTime::from_unix_timestamp(1_684_000_000).unwrap();
+        let limit = RateLimit::parse(
+            &headers(&[
+                ("ratelimit-reset", "1684000090"),
+                ("ratelimit-remaining", "12"),
+            ]),
+            now,
+        );
+
+        assert_eq!(limit.retry_after, Some(Duration::from_secs(90)));
+        assert_eq!(limit.remaining, Some(12));
+    }
+
+    #[test]
+    fn gives_up_after_max_attempts() {
+        let policy = RetryPolicy::exponential(3);
+
+        assert!(policy.delay(1, None).is_some());
+        assert!(policy.delay(2, None).unwrap() <= Duration::from_secs(1));
+        assert_eq!(policy.delay(3, None), None);
+        assert_eq!(policy.delay(1, Some(Duration::from_secs(3600))), None);
+        assert_eq!(RetryPolicy::none().delay(1, None), None);
+    }
+}
@@ -0,0 +1,217 @@
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::{event, Level};
+
+use super::XrpcClient;
+
+/// Longest hashtag, in characters.
+const MAX_TAG_LENGTH: usize = 64;
+
+/// `app.bsky.richtext.facet`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Facet {
+    pub index: ByteSlice,
+    pub features: Vec<FacetFeature>,
+}
+
+/// `app.bsky.richtext.facet#byteSlice`, a range of UTF-8 bytes in the text.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ByteSlice {
+    pub byte_start: usize,
+    pub byte_end: usize,
+}
+
+impl From<Range<usize>> for ByteSlice {
+    fn from(range: Range<usize>) -> Self {
+        Self {
+            byte_start: range.start,
+            byte_end: range.end,
+        }
+    }
+}
+
+/// What a facet annotates. Features of other types are kept as
+/// [`FacetFeature::Unknown`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "$type")]
+pub enum FacetFeature {
+    #[serde(rename = "app.bsky.richtext.facet#mention")]
+    Mention { did: String },
+    #[serde(rename = "app.bsky.richtext.facet#link")]
+    Link { uri: String },
+    /// A hashtag, with the tag itself stored without the leading `#`.
+    #[serde(rename = "app.bsky.richtext.facet#tag")]
+    Tag { tag: String },
+    #[serde(untagged)]
+    Unknown(Value),
+}
+
+/// Post text along with its facets.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RichText {
+    pub text: String,
+    pub facets: Vec<Facet>,
+}
+
+impl RichText {
+    /// Text without any facets.
+    pub fn plain(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            facets: Vec::new(),
+        }
+    }
+
+    /// Detects the mentions, links and tags in `text`. Mentions of handles
+    /// which can't be resolved are left as plain text.
+    pub async fn detect(client: &XrpcClient, text: impl Into<String>) -> Self {
+        let text = text.into();
+        let mut facets = Vec::new();
+
+        for segment in segments(&text) {
+            let feature = match segment.kind {
+                SegmentKind::Mention(handle) => match client.resolve_handle(handle).await {
+                    Ok(did) => FacetFeature::Mention { did },
+                    Err(e) => {
+                        event!(Level::WARN, "Failed to resolve @{}: {:#}", handle, e);
+                        continue;
+                    }
+                },
+                SegmentKind::Link(uri) => FacetFeature::Link {
+                    uri: uri.to_owned(),
+                },
+                SegmentKind::Tag(tag) => FacetFeature::Tag {
+                    tag: tag.to_owned(),
+                },
+            };
+
+            facets.push(Facet {
+                index: segment.range.into(),
+                features: vec![feature],
+            });
+        }
+
+        Self { text, facets }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum SegmentKind<'a> {
+    Mention(&'a str),
+    Link(&'a str),
+    Tag(&'a str),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Segment<'a> {
+    range: Range<usize>,
+    kind: SegmentKind<'a>,
+}
+
+/// Finds the segments of `text` which should become facets, with their byte
+/// ranges.
+fn segments(text: &str) -> Vec<Segment<'_>> {
+    words(text)
+        .filter_map(|(start, word)| {
+            // Punctuation around a word is most likely part of the sentence.
+            let trimmed = word.trim_start_matches(['(', '[', '"', '\'']);
+            let start = start + word.len() - trimmed.len();
+            let word =
+                trimmed.trim_end_matches(['.', ',', ';', ':', '!', '?', ')', ']', '"', '\'']);
+            let range = start..start + word.len();
+
+            let kind = if word.starts_with("https://") || word.starts_with("http://") {
+                let (_, rest) = word.split_once("://")?;
+                (!rest.is_empty()).then_some(SegmentKind::Link(word))?
+            } else if let Some(handle) = word.strip_prefix('@') {
+                is_handle(handle).then_some(SegmentKind::Mention(handle))?
+            } else if let Some(tag) = word.strip_prefix('#') {
+                is_tag(tag).then_some(SegmentKind::Tag(tag))?
+            } else {
+                return None;
+            };
+
+            Some(Segment { range, kind })
+        })
+        .collect()
+}
+
+/// The whitespace separated words of `text`, with their byte offsets.
+fn words(text: &str) -> impl Iterator<Item = (usize, &str)> {
+    text.split(char::is_whitespace)
+        .filter(|it| !it.is_empty())
+        .map(move |word| (word.as_ptr() as usize - text.as_ptr() as usize, word))
+}
+
+fn is_handle(handle: &str) -> bool {
+    let labels = handle.split('.').collect::<Vec<_>>();
+
+    labels.len() >= 2
+        && labels.iter().all(|label| {
+            !label.is_empty()
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label
+                    .chars()
+                    .all(|it| it.is_ascii_alphanumeric() || it == '-')
+        })
+        && labels
+            .last()
+            .is_some_and(|it| it.starts_with(|c: char| c.is_ascii_alphabetic()))
+}
+
+fn is_tag(tag: &str) -> bool {
+    !tag.is_empty()
+        && tag.chars().count() <= MAX_TAG_LENGTH
+        && !tag.chars().all(|it| it.is_ascii_digit())
+        && tag.chars().all(|it| it.is_alphanumeric() || it == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_segments_with_byte_offsets() {
+        let text = "✨ Hi @alice.bsky.social, see (https://example.com/a?b=c). #rust #1";
+        let segments = segments(text);
+
+        assert_eq!(
+            segments
+                .iter()
+                .map(|it| &text[it.range.clone()])
+                .collect::<Vec<_>>(),
+            vec!["@alice.bsky.social", "https://example.com/a?b=c", "#rust"]
+        );
+        assert_eq!(segments[0].range, 7..25);
+        assert_eq!(segments[0].kind, SegmentKind::Mention("alice.bsky.social"));
+        assert_eq!(segments[2].kind, SegmentKind::Tag("rust"));
+    }
+
+    #[test]
+    fn ignores_invalid_handles_and_tags() {
+        assert!(segments("@alice @bsky. @-a.com mail@a.com # #a#b http://").is_empty());
+    }
+
+    #[test]
+    fn serializes_features_with_type() {
+        let facet = Facet {
+            index: (0..5).into(),
+            features: vec![FacetFeature::Tag {
+                tag: "rust".to_owned(),
+            }],
+        };
+
+        assert_eq!(
+            serde_json::to_value(facet).unwrap(),
+            serde_json::json!({
+                "index": { "byteStart": 0, "byteEnd": 5 },
+                "features": [{ "$type": "app.bsky.richtext.facet#tag", "tag": "rust" }],
+            })
+        );
+    }
+}
@@ -0,0 +1,123 @@
+use std::fmt::Debug;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use super::XrpcAuth;
+
+/// Storage for the tokens of an XRPC session, allowing a session to be resumed
+/// after the process restarts instead of logging in with a password again.
+pub trait SessionStore: Debug + Send + Sync {
+    /// Loads the previously saved session, if there is one.
+    fn load(&self) -> io::Result<Option<XrpcAuth>>;
+
+    /// Saves the session, replacing any previously saved session.
+    fn save(&self, auth: &XrpcAuth) -> io::Result<()>;
+
+    /// Removes the saved session.
+    fn clear(&self) -> io::Result<()>;
+}
+
+/// Session store keeping the session as JSON in a single file on disk.
+#[derive(Debug, Clone)]
+pub struct FileSessionStore {
+    path: PathBuf,
+}
+
+impl FileSessionStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl SessionStore for FileSessionStore {
+    fn load(&self) -> io::Result<Option<XrpcAuth>> {
+        let contents = match fs::read_to_string(&self.path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+
+        let auth = serde_json::from_str(&contents)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        Ok(Some(auth))
+    }
+
+    fn save(&self, auth: &XrpcAuth) -> io::Result<()> {
+        let contents = serde_json::to_vec_pretty(auth)?;
+
+        // Writing to a temporary file first so a crash can't leave a half
+        // written session behind.
+        let temp = self.path.with_extension("tmp");
+        let mut file = open_private(&temp)?;
+        file.write_all(&contents)?;
+        file.sync_all()?;
+
+        fs::rename(&temp, &self.path)
+    }
+
+    fn clear(&self) -> io::Result<()> {
+        match fs::remove_file(&self.path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Opens a file for writing which is only readable by the current user, as it
+/// will contain credentials.
+fn open_private(path: &Path) -> io::Result<fs::File> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    options.open(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use super::*;
+
+    #[test]
+    fn saves_loads_and_clears_the_session() {
+        let path = env::temp_dir().join(format!("gptbot-session-{}.json", process::id()));
+        let store = FileSessionStore::new(&path);
+        let auth = XrpcAuth {
+            access_jwt: "access".to_owned(),
+            refresh_jwt: "refresh".to_owned(),
+            did: "did:plc:bot".to_owned(),
+        };
+
+        assert!(store.load().unwrap().is_none());
+
+        store.save(&auth).unwrap();
+        let loaded = store.load().unwrap().unwrap();
+        assert_eq!(loaded.refresh_jwt, "refresh");
+        assert_eq!(loaded.did, "did:plc:bot");
+        assert!(!path.with_extension("tmp").exists());
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        store.clear().unwrap();
+        assert!(store.load().unwrap().is_none());
+        // Clearing a missing session is fine too.
+        store.clear().unwrap();
+    }
+}
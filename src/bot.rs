@@ -0,0 +1,267 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use time::OffsetDateTime;
+use tracing::{event, Level};
+
+use crate::atp::{GetPostThreadParams, PostView, ReplyRef, ThreadView, XrpcClient};
+use crate::config::Config;
+use crate::ledger::{Claim, RequestLedger, RequestStatus};
+use crate::llm::{ChatMessage, ChatRequest, ChatRole, LanguageModel, LanguageModelError};
+use crate::persona::{PersonaRegistry, PromptContext};
+use crate::post_length;
+use crate::thread::{Conversation, ThreadLimits};
+
+#[derive(Debug)]
+pub struct BotRequest {
+    pub uri: String,
+}
+
+#[derive(Debug)]
+pub enum BotRequestResult {
+    Success,
+    InvalidRequest,
+    /// The post the mention replies to was deleted.
+    ParentDeleted,
+    /// The post the mention replies to is hidden by a block.
+    ParentBlocked,
+}
+
+/// Everything needed to respond to requests. Cloning is cheap, so every
+/// request task gets its own copy.
+#[derive(Debug, Clone)]
+pub struct Bot {
+    pub client: XrpcClient,
+    pub model: Arc<dyn LanguageModel>,
+    pub ledger: RequestLedger,
+    pub personas: Arc<PersonaRegistry>,
+    pub config: Arc<Config>,
+}
+
+impl Bot {
+    /// Processes a request unless the ledger shows it was already handled, and
+    /// records the outcome. Failing to process the request is recorded and
+    /// logged, so errors only mean the outcome couldn't be recorded.
+    pub async fn handle_request(&self, request: BotRequest) -> Result<()> {
+        let Some(claim) = self.ledger.begin(&request.uri).await? else {
+            let status = self.ledger.status(&request.uri).await?;
+            event!(
+                Level::INFO,
+                "Skipping request for {} ({:?})",
+                request.uri,
+                status
+            );
+            return Ok(());
+        };
+
+        // Timing out here rather than around the whole task, so the request is
+        // still recorded as failed and retried later.
+        let uri = request.uri.clone();
+        let timeout = Duration::from_secs(self.config.bot.request_timeout_secs);
+        let processing = self.process_request(request, claim);
+        let result = match tokio::time::timeout(timeout, processing).await {
+            Ok(result) => result,
+            Err(_) => Err(anyhow!("Timed out after {timeout:?}")),
+        };
+
+        let status = match &result {
+            Ok(BotRequestResult::Success) => RequestStatus::Success,
+            Ok(BotRequestResult::InvalidRequest) => RequestStatus::InvalidRequest,
+            Ok(BotRequestResult::ParentDeleted) => RequestStatus::ParentDeleted,
+            Ok(BotRequestResult::ParentBlocked) => RequestStatus::ParentBlocked,
+            Err(e) => RequestStatus::Failed(format!("{e:#}")),
+        };
+
+        self.ledger.finish(&uri, status).await?;
+
+        if let Err(e) = result {
+            event!(Level::ERROR, "Failed to respond to {}: {:#}", uri, e);
+        }
+
+        Ok(())
+    }
+
+    async fn process_request(&self, request: BotRequest, claim: Claim) -> Result<BotRequestResult> {
+        event!(Level::INFO, "Processing request for {}", request.uri);
+
+        // Retries need the replies, to find one posted by an earlier attempt
+        let depth = match claim {
+            Claim::New => 0,
+            Claim::Retry => 1,
+        };
+        let thread = self
+            .client
+            .get_post_thread(GetPostThreadParams {
+                uri: request.uri,
+                depth: Some(depth),
+            })
+            .await?
+            .thread;
+
+        let ThreadView::Post(thread) = thread else {
+            event!(Level::WARN, "Invalid request. Child post not found");
+            return Ok(BotRequestResult::InvalidRequest);
+        };
+
+        let bot_did = self.client.did().unwrap_or_default();
+        let replied = thread.replies.iter().any(|reply| match reply {
+            ThreadView::Post(reply) => reply.post.author.did == bot_did,
+            _ => false,
+        });
+        if replied {
+            event!(Level::INFO, "Already replied to {}", thread.post.uri);
+            return Ok(BotRequestResult::Success);
+        }
+
+        match thread.parent.as_deref() {
+            Some(ThreadView::Post(_)) => {}
+            Some(ThreadView::NotFound(_)) => {
+                event!(Level::WARN, "Invalid request. Parent post was deleted");
+                return Ok(BotRequestResult::ParentDeleted);
+            }
+            Some(ThreadView::Blocked(_)) => {
+                event!(Level::WARN, "Invalid request. Parent post is blocked");
+                return Ok(BotRequestResult::ParentBlocked);
+            }
+            Some(ThreadView::Unknown(_)) => {
+                event!(Level::WARN, "Invalid request. Parent post can't be shown");
+                return Ok(BotRequestResult::InvalidRequest);
+            }
+            None => {
+                event!(Level::WARN, "Invalid request. Child post isn't a reply");
+                return Ok(BotRequestResult::InvalidRequest);
+            }
+        }
+
+        let child = thread.post.clone();
+
+        let limits = ThreadLimits {
+            max_depth: self.config.bot.max_thread_depth,
+            max_tokens: self.config.bot.max_context_tokens,
+        };
+
+        let Some(conversation) = Conversation::from_thread(*thread, &bot_did, limits) else {
+            event!(Level::WARN, "Invalid request. Parent post has no text");
+            return Ok(BotRequestResult::InvalidRequest);
+        };
+
+        let Some(response) = self
+            .generate_response(&conversation, &child, &bot_did)
+            .await?
+        else {
+            return Ok(BotRequestResult::InvalidRequest);
+        };
+
+        let parts = post_length::split_with_signature(
+            &response,
+            &self.config.bot.signature,
+            self.config.bot.max_response_length,
+            self.config.bot.max_reply_parts,
+        );
+
+        let mut reply_ref = ReplyRef::to(&child);
+        for (i, part) in parts.into_iter().enumerate() {
+            let reply = match self.client.post_reply(reply_ref.clone(), part).await {
+                Ok(reply) => reply,
+                Err(e) if i == 0 => return Err(e.into()),
+                // Failing the request now would reply again when it's retried.
+                Err(e) => {
+                    event!(
+                        Level::ERROR,
+                        "Failed to post part {} of the reply to {}: {:#}",
+                        i + 1,
+                        child.uri,
+                        e
+                    );
+                    break;
+                }
+            };
+
+            event!(
+                Level::INFO,
+                "Fulfilled request for {}.\nURI: {}",
+                child.author.handle,
+                reply.uri
+            );
+
+            reply_ref.parent = reply;
+        }
+
+        Ok(BotRequestResult::Success)
+    }
+
+    /// Generates a response to the conversation's target, using the persona
+    /// picked by the mention in `request`.
+    async fn generate_response(
+        &self,
+        conversation: &Conversation,
+        request: &PostView,
+        bot_did: &str,
+    ) -> Result<Option<String>> {
+        let post = &conversation.target;
+        let Some(text) = post.record.text() else {
+            return Ok(None);
+        };
+
+        let persona = self
+            .personas
+            .select(request.record.text().unwrap_or_default());
+
+        let context = PromptContext {
+            author_handle: post.author.handle.clone(),
+            author_display_name: post
+                .author
+                .display_name
+                .clone()
+                .unwrap_or_else(|| post.author.handle.clone()),
+            post_text: text.to_owned(),
+            thread_context: conversation.transcript(),
+            date: OffsetDateTime::now_utc().date().to_string(),
+            bot_handle: self.config.bluesky.handle.clone(),
+        };
+
+        event!(
+            Level::INFO,
+            "Using persona '{}' with {} earlier posts for {}",
+            persona.name,
+            conversation.history.len(),
+            request.uri
+        );
+
+        let mut messages = vec![ChatMessage::new(
+            ChatRole::System,
+            persona.system.render(&context),
+        )];
+        messages.extend(conversation.messages(bot_did));
+        messages.push(ChatMessage::new(
+            ChatRole::User,
+            persona.prompt.render(&context),
+        ));
+
+        let request = ChatRequest {
+            messages,
+            user: Some(post.author.did.to_owned()),
+            max_tokens: Some(self.config.llm.max_tokens),
+            temperature: Some(self.config.llm.temperature),
+        };
+
+        let completion = match self.model.complete(&request).await {
+            Ok(completion) => completion,
+            Err(LanguageModelError::Empty) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let message = completion.content;
+
+        event!(
+            Level::INFO,
+            "Spent {} tokens generating response of length {} to @{}\n\"{}\"",
+            completion.total_tokens.unwrap_or_default(),
+            message.len(),
+            post.author.handle,
+            message,
+        );
+
+        Ok(Some(message))
+    }
+}
@@ -0,0 +1,514 @@
+use std::fmt::{Debug, Display};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::{env, fs};
+
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::post_length::{grapheme_count, MAX_POST_GRAPHEMES};
+
+/// Path of the configuration file when `BOT_CONFIG` isn't set.
+const DEFAULT_CONFIG_FILE: &str = "config.toml";
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("Failed to read '{}'", path.display())]
+    Read {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("Failed to parse '{}'", path.display())]
+    Parse {
+        path: PathBuf,
+        #[source]
+        source: toml::de::Error,
+    },
+    #[error("Environment variable {name} is invalid: {reason}")]
+    Env { name: &'static str, reason: String },
+    #[error("Invalid configuration:\n  - {}", .0.join("\n  - "))]
+    Invalid(Vec<String>),
+}
+
+/// A secret value, kept out of debug output.
+#[derive(Clone, Default, Deserialize)]
+#[serde(transparent)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Debug for Secret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Secret(***)")
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub bluesky: BlueskyConfig,
+    pub llm: LlmConfig,
+    pub bot: BotConfig,
+    pub persona: PersonaConfig,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BlueskyConfig {
+    pub provider: String,
+    pub handle: String,
+    pub password: Option<Secret>,
+    /// File to read the password from, instead of putting it in the config.
+    pub password_file: Option<PathBuf>,
+    pub session_file: PathBuf,
+    /// Attempts per XRPC request before giving up, including the first.
+    pub max_request_attempts: u32,
+    /// Time limit for a single XRPC request.
+    pub request_timeout_secs: u64,
+}
+
+impl Default for BlueskyConfig {
+    fn default() -> Self {
+        Self {
+            provider: "https://bsky.social".to_owned(),
+            handle: String::new(),
+            password: None,
+            password_file: None,
+            session_file: PathBuf::from("session.json"),
+            max_request_attempts: 4,
+            request_timeout_secs: 30,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LlmBackend {
+    OpenAi,
+    Canned,
+}
+
+impl FromStr for LlmBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "openai" => Ok(Self::OpenAi),
+            "canned" => Ok(Self::Canned),
+            other => Err(format!(
+                "unknown backend '{other}', expected 'openai' or 'canned'"
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LlmConfig {
+    pub backend: LlmBackend,
+    pub base_url: String,
+    pub api_key: Option<Secret>,
+    /// File to read the API key from, instead of putting it in the config.
+    pub api_key_file: Option<PathBuf>,
+    pub model: String,
+    pub max_tokens: u32,
+    pub temperature: f32,
+    /// Time limit for a single completion request.
+    pub request_timeout_secs: u64,
+    /// Response of the canned backend, which echoes the prompt when unset.
+    pub canned_response: Option<String>,
+}
+
+impl Default for LlmConfig {
+    fn default() -> Self {
+        Self {
+            backend: LlmBackend::OpenAi,
+            base_url: "https://api.openai.com/v1".to_owned(),
+            api_key: None,
+            api_key_file: None,
+            model: "gpt-3.5-turbo-0301".to_owned(),
+            max_tokens: 80,
+            temperature: 0.7,
+            request_timeout_secs: 60,
+            canned_response: None,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BotConfig {
+    pub poll_interval_secs: u64,
+    pub max_concurrent_requests: usize,
+    /// Time limit for processing a request, after which it counts as failed.
+    pub request_timeout_secs: u64,
+    /// Maximum length of a generated response, in graphemes.
+    pub max_response_length: usize,
+    /// Longer responses are split into up to this many replies, threaded
+    /// under each other. With 1 they're truncated instead.
+    pub max_reply_parts: usize,
+    /// Appended to every response.
+    pub signature: String,
+    pub state_file: PathBuf,
+    pub ledger_file: PathBuf,
+    /// Attempts for processing a request before giving up, including the
+    /// first.
+    pub max_process_attempts: u32,
+    /// Most posts above a mention given to the model as context.
+    pub max_thread_depth: usize,
+    /// Rough budget for the thread context, in tokens.
+    pub max_context_tokens: usize,
+}
+
+impl Default for BotConfig {
+    fn default() -> Self {
+        Self {
+            poll_interval_secs: 20,
+            max_concurrent_requests: 4,
+            request_timeout_secs: 300,
+            max_response_length: 280,
+            max_reply_parts: 1,
+            signature: "\n\n🤖 info in bio".to_owned(),
+            state_file: PathBuf::from("state.json"),
+            ledger_file: PathBuf::from("ledger.sqlite3"),
+            max_process_attempts: 3,
+            max_thread_depth: 16,
+            max_context_tokens: 1000,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PersonaConfig {
+    /// Directory of `<name>.toml` persona files, reloaded when it changes.
+    pub directory: PathBuf,
+    /// Persona used for mentions that don't match any persona's keywords.
+    pub default: String,
+}
+
+impl Default for PersonaConfig {
+    fn default() -> Self {
+        Self {
+            directory: PathBuf::from("personas"),
+            default: "cody".to_owned(),
+        }
+    }
+}
+
+impl Config {
+    /// Loads the configuration from the file in `BOT_CONFIG`, or
+    /// `config.toml` if it exists, then applies the environment overrides,
+    /// reads the secret files and validates the result.
+    pub fn load() -> Result<Self, ConfigError> {
+        let mut config = match env_var("BOT_CONFIG") {
+            Some(path) => Self::from_file(path)?,
+            None if Path::new(DEFAULT_CONFIG_FILE).exists() => {
+                Self::from_file(DEFAULT_CONFIG_FILE)?
+            }
+            None => Self::default(),
+        };
+
+        config.apply_env()?;
+        config.read_secrets()?;
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path).map_err(|source| ConfigError::Read {
+            path: path.to_owned(),
+            source,
+        })?;
+
+        toml::from_str(&contents).map_err(|source| ConfigError::Parse {
+            path: path.to_owned(),
+            source,
+        })
+    }
+
+    fn apply_env(&mut self) -> Result<(), ConfigError> {
+        override_string("BLUESKY_PROVIDER", &mut self.bluesky.provider);
+        override_string("BLUESKY_HANDLE", &mut self.bluesky.handle);
+        override_secret("BLUESKY_PASSWORD", &mut self.bluesky.password);
+        override_path("BLUESKY_PASSWORD_FILE", &mut self.bluesky.password_file);
+        override_parsed("BLUESKY_SESSION_FILE", &mut self.bluesky.session_file)?;
+        override_parsed(
+            "BLUESKY_MAX_REQUEST_ATTEMPTS",
+            &mut self.bluesky.max_request_attempts,
+        )?;
+        override_parsed(
+            "BLUESKY_REQUEST_TIMEOUT_SECS",
+            &mut self.bluesky.request_timeout_secs,
+        )?;
+
+        override_parsed("LLM_BACKEND", &mut self.llm.backend)?;
+        override_string("OPENAI_BASE_URL", &mut self.llm.base_url);
+        override_secret("OPENAI_KEY", &mut self.llm.api_key);
+        override_path("OPENAI_KEY_FILE", &mut self.llm.api_key_file);
+        override_string("OPENAI_MODEL", &mut self.llm.model);
+        override_parsed("LLM_MAX_TOKENS", &mut self.llm.max_tokens)?;
+        override_parsed("LLM_TEMPERATURE", &mut self.llm.temperature)?;
+        override_parsed(
+            "LLM_REQUEST_TIMEOUT_SECS",
+            &mut self.llm.request_timeout_secs,
+        )?;
+        override_optional("LLM_CANNED_RESPONSE", &mut self.llm.canned_response);
+
+        override_parsed("BOT_POLL_INTERVAL_SECS", &mut self.bot.poll_interval_secs)?;
+        override_parsed(
+            "BOT_MAX_CONCURRENT_REQUESTS",
+            &mut self.bot.max_concurrent_requests,
+        )?;
+        override_parsed(
+            "BOT_REQUEST_TIMEOUT_SECS",
+            &mut self.bot.request_timeout_secs,
+        )?;
+        override_parsed("BOT_MAX_RESPONSE_LENGTH", &mut self.bot.max_response_length)?;
+        override_parsed("BOT_MAX_REPLY_PARTS", &mut self.bot.max_reply_parts)?;
+        override_string("BOT_SIGNATURE", &mut self.bot.signature);
+        override_parsed("BOT_STATE_FILE", &mut self.bot.state_file)?;
+        override_parsed("BOT_LEDGER_FILE", &mut self.bot.ledger_file)?;
+        override_parsed(
+            "BOT_MAX_PROCESS_ATTEMPTS",
+            &mut self.bot.max_process_attempts,
+        )?;
+        override_parsed("BOT_MAX_THREAD_DEPTH", &mut self.bot.max_thread_depth)?;
+        override_parsed("BOT_MAX_CONTEXT_TOKENS", &mut self.bot.max_context_tokens)?;
+
+        override_parsed("PERSONA_DIRECTORY", &mut self.persona.directory)?;
+        override_string("PERSONA_DEFAULT", &mut self.persona.default);
+
+        Ok(())
+    }
+
+    fn read_secrets(&mut self) -> Result<(), ConfigError> {
+        if let Some(path) = &self.bluesky.password_file {
+            self.bluesky.password = Some(read_secret(path)?);
+        }
+
+        if let Some(path) = &self.llm.api_key_file {
+            self.llm.api_key = Some(read_secret(path)?);
+        }
+
+        Ok(())
+    }
+
+    /// Checks every setting, reporting all problems at once.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let mut problems = Vec::new();
+
+        if !is_http_url(&self.bluesky.provider) {
+            problems.push(format!(
+                "bluesky.provider must be an http(s) URL, got '{}'",
+                self.bluesky.provider
+            ));
+        }
+
+        if self.bluesky.handle.trim().is_empty() {
+            problems.push("bluesky.handle must be set (or BLUESKY_HANDLE)".to_owned());
+        }
+
+        if self
+            .bluesky
+            .password
+            .as_ref()
+            .is_none_or(|it| it.expose().is_empty())
+        {
+            problems.push(
+                "bluesky.password or bluesky.password_file must be set (or BLUESKY_PASSWORD)"
+                    .to_owned(),
+            );
+        }
+
+        if self.bluesky.max_request_attempts == 0 {
+            problems.push("bluesky.max_request_attempts must be at least 1".to_owned());
+        }
+
+        if self.bluesky.request_timeout_secs == 0 {
+            problems.push("bluesky.request_timeout_secs must be at least 1".to_owned());
+        }
+
+        if self.llm.backend == LlmBackend::OpenAi {
+            if !is_http_url(&self.llm.base_url) {
+                problems.push(format!(
+                    "llm.base_url must be an http(s) URL, got '{}'",
+                    self.llm.base_url
+                ));
+            }
+
+            if self.llm.model.trim().is_empty() {
+                problems.push("llm.model must be set".to_owned());
+            }
+        }
+
+        if self.llm.request_timeout_secs == 0 {
+            problems.push("llm.request_timeout_secs must be at least 1".to_owned());
+        }
+
+        if self.llm.max_tokens == 0 {
+            problems.push("llm.max_tokens must be at least 1".to_owned());
+        }
+
+        if !(0.0..=2.0).contains(&self.llm.temperature) {
+            problems.push(format!(
+                "llm.temperature must be between 0 and 2, got {}",
+                self.llm.temperature
+            ));
+        }
+
+        if self.bot.poll_interval_secs == 0 {
+            problems.push("bot.poll_interval_secs must be at least 1".to_owned());
+        }
+
+        if self.bot.max_concurrent_requests == 0 {
+            problems.push("bot.max_concurrent_requests must be at least 1".to_owned());
+        }
+
+        if self.bot.request_timeout_secs == 0 {
+            problems.push("bot.request_timeout_secs must be at least 1".to_owned());
+        }
+
+        if self.bot.max_process_attempts == 0 {
+            problems.push("bot.max_process_attempts must be at least 1".to_owned());
+        }
+
+        if self.bot.max_reply_parts == 0 {
+            problems.push("bot.max_reply_parts must be at least 1".to_owned());
+        }
+
+        if self.bot.max_thread_depth == 0 {
+            problems.push("bot.max_thread_depth must be at least 1".to_owned());
+        }
+
+        let signature_length = grapheme_count(&self.bot.signature);
+        if self.bot.max_response_length == 0
+            || self.bot.max_response_length + signature_length > MAX_POST_GRAPHEMES
+        {
+            problems.push(format!(
+                "bot.max_response_length must be between 1 and {} so the response and signature \
+                 fit in a post, got {}",
+                MAX_POST_GRAPHEMES.saturating_sub(signature_length),
+                self.bot.max_response_length
+            ));
+        }
+
+        if self.persona.default.trim().is_empty() {
+            problems.push("persona.default must be set".to_owned());
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigError::Invalid(problems))
+        }
+    }
+}
+
+fn is_http_url(value: &str) -> bool {
+    value.starts_with("https://") || value.starts_with("http://")
+}
+
+fn read_secret(path: &Path) -> Result<Secret, ConfigError> {
+    let secret = fs::read_to_string(path).map_err(|source| ConfigError::Read {
+        path: path.to_owned(),
+        source,
+    })?;
+
+    Ok(Secret(secret.trim().to_owned()))
+}
+
+/// Reads an environment variable, treating empty values as unset so an
+/// unfilled `.env` doesn't clear the config file's settings.
+fn env_var(name: &str) -> Option<String> {
+    env::var(name).ok().filter(|it| !it.is_empty())
+}
+
+fn override_string(name: &'static str, value: &mut String) {
+    if let Some(var) = env_var(name) {
+        *value = var;
+    }
+}
+
+fn override_secret(name: &'static str, value: &mut Option<Secret>) {
+    if let Some(var) = env_var(name) {
+        *value = Some(Secret(var));
+    }
+}
+
+fn override_optional(name: &'static str, value: &mut Option<String>) {
+    if let Some(var) = env_var(name) {
+        *value = Some(var);
+    }
+}
+
+fn override_path(name: &'static str, value: &mut Option<PathBuf>) {
+    if let Some(var) = env_var(name) {
+        *value = Some(PathBuf::from(var));
+    }
+}
+
+fn override_parsed<T>(name: &'static str, value: &mut T) -> Result<(), ConfigError>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    if let Some(var) = env_var(name) {
+        *value = var.parse().map_err(|e: T::Err| ConfigError::Env {
+            name,
+            reason: e.to_string(),
+        })?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_example_config() {
+        let config = toml::from_str::<Config>(include_str!("../config.example.toml")).unwrap();
+
+        assert_eq!(config.bluesky.handle, "bot.bsky.social");
+        assert_eq!(config.llm.backend, LlmBackend::OpenAi);
+        assert_eq!(config.bot.poll_interval_secs, 20);
+        assert_eq!(config.persona.default, "cody");
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn reports_every_invalid_setting() {
+        let config = toml::from_str::<Config>(
+            r#"
+            [bluesky]
+            provider = "bsky.social"
+
+            [llm]
+            temperature = 3.0
+            "#,
+        )
+        .unwrap();
+
+        let Err(ConfigError::Invalid(problems)) = config.validate() else {
+            panic!("Config should be invalid");
+        };
+
+        assert_eq!(problems.len(), 4);
+        assert!(problems[0].starts_with("bluesky.provider"));
+        assert!(problems[3].starts_with("llm.temperature"));
+    }
+
+    #[test]
+    fn rejects_unknown_settings() {
+        assert!(toml::from_str::<Config>("[bot]\npoll_interval = 5").is_err());
+    }
+}
@@ -0,0 +1,369 @@
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::Result;
+use rusqlite::{params, Connection, OptionalExtension};
+use tracing::{event, Level};
+
+/// Delay before the first retry of a request, doubled for every attempt after.
+const RETRY_DELAY: Duration = Duration::from_secs(60);
+
+/// Outcome of processing a request, as recorded in the ledger.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RequestStatus {
+    /// The request is being processed.
+    InProgress,
+    /// The process stopped while the request was being processed, so it is
+    /// unknown whether a reply was posted. These are retried like failures,
+    /// as a [`Claim::Retry`].
+    Interrupted,
+    Success,
+    InvalidRequest,
+    ParentDeleted,
+    ParentBlocked,
+    Failed(String),
+}
+
+impl RequestStatus {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::InProgress => "in_progress",
+            Self::Interrupted => "interrupted",
+            Self::Success => "success",
+            Self::InvalidRequest => "invalid_request",
+            Self::ParentDeleted => "parent_deleted",
+            Self::ParentBlocked => "parent_blocked",
+            Self::Failed(_) => "failed",
+        }
+    }
+
+    fn error(&self) -> Option<&str> {
+        match self {
+            Self::Failed(error) => Some(error),
+            _ => None,
+        }
+    }
+
+    fn from_row(name: &str, error: Option<String>) -> Self {
+        match name {
+            "in_progress" => Self::InProgress,
+            "interrupted" => Self::Interrupted,
+            "success" => Self::Success,
+            "invalid_request" => Self::InvalidRequest,
+            "parent_deleted" => Self::ParentDeleted,
+            "parent_blocked" => Self::ParentBlocked,
+            _ => Self::Failed(error.unwrap_or_default()),
+        }
+    }
+}
+
+/// How a request was claimed with [`RequestLedger::begin`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Claim {
+    /// The request was never attempted before.
+    New,
+    /// An earlier attempt failed or was interrupted, possibly after posting a
+    /// reply, so the thread should be checked for one first.
+    Retry,
+}
+
+/// Persistent record of every request the bot has processed, used to make
+/// sure a mention is replied to at most once, and to retry failed requests a
+/// bounded number of times with an exponential backoff.
+///
+/// The connection is only used from blocking tasks, so the queries don't stall
+/// the runtime.
+#[derive(Debug, Clone)]
+pub struct RequestLedger {
+    connection: Arc<Mutex<Connection>>,
+    max_attempts: u32,
+    retry_delay: Duration,
+}
+
+impl RequestLedger {
+    pub fn open(path: impl AsRef<Path>, max_attempts: u32) -> Result<Self> {
+        Self::from_connection(Connection::open(path)?, max_attempts)
+    }
+
+    #[cfg(test)]
+    pub fn open_in_memory(max_attempts: u32) -> Result<Self> {
+        Self::from_connection(Connection::open_in_memory()?, max_attempts)
+    }
+
+    fn from_connection(connection: Connection, max_attempts: u32) -> Result<Self> {
+        connection.execute_batch(
+            "CREATE TABLE IF NOT EXISTS requests (
+                uri TEXT PRIMARY KEY NOT NULL,
+                status TEXT NOT NULL,
+                error TEXT,
+                attempts INTEGER NOT NULL,
+                updated_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                next_attempt_at TEXT
+            );",
+        )?;
+
+        // Ledgers created before retries were delayed don't have the column
+        let has_next_attempt: bool = connection.query_row(
+            "SELECT COUNT(*) > 0 FROM pragma_table_info('requests')
+             WHERE name = 'next_attempt_at'",
+            [],
+            |row| row.get(0),
+        )?;
+        if !has_next_attempt {
+            connection.execute_batch("ALTER TABLE requests ADD COLUMN next_attempt_at TEXT;")?;
+        }
+
+        // Requests still in progress were interrupted by the process stopping.
+        let interrupted = connection.execute(
+            "UPDATE requests SET status = ?1, updated_at = CURRENT_TIMESTAMP WHERE status = ?2",
+            params![
+                RequestStatus::Interrupted.name(),
+                RequestStatus::InProgress.name()
+            ],
+        )?;
+
+        if interrupted > 0 {
+            event!(
+                Level::WARN,
+                "{} requests were interrupted and will be retried if not replied to",
+                interrupted
+            );
+        }
+
+        Ok(Self {
+            connection: Arc::new(Mutex::new(connection)),
+            max_attempts,
+            retry_delay: RETRY_DELAY,
+        })
+    }
+
+    /// Runs `f` with the connection on a blocking task.
+    async fn with_connection<T, F>(&self, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Connection) -> Result<T> + Send + 'static,
+    {
+        let connection = self.connection.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut connection = connection
+                .lock()
+                .expect("Ledger lock should not be poisoned");
+            f(&mut connection)
+        })
+        .await?
+    }
+
+    /// Claims a request for processing. Returns `None` if the request was
+    /// already handled, is being handled, has no attempts left or isn't due
+    /// for a retry yet.
+    pub async fn begin(&self, uri: &str) -> Result<Option<Claim>> {
+        let uri = uri.to_owned();
+        let max_attempts = self.max_attempts;
+
+        self.with_connection(move |connection| {
+            let transaction = connection.transaction()?;
+
+            let existing = transaction
+                .query_row(
+                    "SELECT status, error, attempts,
+                            IFNULL(next_attempt_at <= CURRENT_TIMESTAMP, 1)
+                     FROM requests WHERE uri = ?1",
+                    params![uri],
+                    |row| {
+                        let status =
+                            RequestStatus::from_row(&row.get::<_, String>(0)?, row.get(1)?);
+                        Ok((status, row.get::<_, u32>(2)?, row.get::<_, bool>(3)?))
+                    },
+                )
+                .optional()?;
+
+            let claim = match existing {
+                None => {
+                    transaction.execute(
+                        "INSERT INTO requests (uri, status, attempts) VALUES (?1, ?2, 1)",
+                        params![uri, RequestStatus::InProgress.name()],
+                    )?;
+                    Some(Claim::New)
+                }
+                Some((RequestStatus::Failed(_) | RequestStatus::Interrupted, attempts, true))
+                    if attempts < max_attempts =>
+                {
+                    transaction.execute(
+                        "UPDATE requests
+                         SET status = ?2, error = NULL, attempts = attempts + 1,
+                             updated_at = CURRENT_TIMESTAMP, next_attempt_at = NULL
+                         WHERE uri = ?1",
+                        params![uri, RequestStatus::InProgress.name()],
+                    )?;
+                    Some(Claim::Retry)
+                }
+                Some(_) => None,
+            };
+
+            transaction.commit()?;
+            Ok(claim)
+        })
+        .await
+    }
+
+    /// Records the outcome of a request claimed with [`RequestLedger::begin`].
+    /// Failed requests become due again after a delay which doubles with every
+    /// attempt.
+    pub async fn finish(&self, uri: &str, status: RequestStatus) -> Result<()> {
+        let uri = uri.to_owned();
+        let delay = self.retry_delay.as_secs();
+
+        self.with_connection(move |connection| {
+            connection.execute(
+                "UPDATE requests
+                 SET status = ?2, error = ?3, updated_at = CURRENT_TIMESTAMP,
+                     next_attempt_at = CASE WHEN ?2 = ?4
+                         THEN datetime('now', '+' || (?5 << (attempts - 1)) || ' seconds')
+                     END
+                 WHERE uri = ?1",
+                params![
+                    uri,
+                    status.name(),
+                    status.error(),
+                    RequestStatus::Failed(String::new()).name(),
+                    delay
+                ],
+            )?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    /// The status of a request, if it was ever claimed.
+    pub async fn status(&self, uri: &str) -> Result<Option<RequestStatus>> {
+        let uri = uri.to_owned();
+
+        self.with_connection(move |connection| {
+            let status = connection
+                .query_row(
+                    "SELECT status, error FROM requests WHERE uri = ?1",
+                    params![uri],
+                    |row| {
+                        Ok(RequestStatus::from_row(
+                            &row.get::<_, String>(0)?,
+                            row.get(1)?,
+                        ))
+                    },
+                )
+                .optional()?;
+
+            Ok(status)
+        })
+        .await
+    }
+
+    /// Failed and interrupted requests which have attempts left and are due
+    /// for a retry.
+    pub async fn retryable(&self) -> Result<Vec<String>> {
+        let max_attempts = self.max_attempts;
+
+        self.with_connection(move |connection| {
+            let mut statement = connection.prepare(
+                "SELECT uri FROM requests
+                 WHERE status IN (?1, ?2) AND attempts < ?3
+                   AND IFNULL(next_attempt_at <= CURRENT_TIMESTAMP, 1)
+                 ORDER BY updated_at",
+            )?;
+
+            let uris = statement
+                .query_map(
+                    params![
+                        RequestStatus::Failed(String::new()).name(),
+                        RequestStatus::Interrupted.name(),
+                        max_attempts
+                    ],
+                    |row| row.get(0),
+                )?
+                .collect::<Result<Vec<String>, _>>()?;
+
+            Ok(uris)
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn claims_requests_at_most_once() {
+        let ledger = RequestLedger::open_in_memory(3).unwrap();
+
+        assert_eq!(ledger.begin("at://a").await.unwrap(), Some(Claim::New));
+        assert_eq!(ledger.begin("at://a").await.unwrap(), None);
+
+        ledger
+            .finish("at://a", RequestStatus::Success)
+            .await
+            .unwrap();
+        assert_eq!(ledger.begin("at://a").await.unwrap(), None);
+        assert_eq!(
+            ledger.status("at://a").await.unwrap(),
+            Some(RequestStatus::Success)
+        );
+    }
+
+    #[tokio::test]
+    async fn retries_failed_requests_a_bounded_number_of_times() {
+        let mut ledger = RequestLedger::open_in_memory(2).unwrap();
+        ledger.retry_delay = Duration::ZERO;
+        let failed = RequestStatus::Failed("timeout".to_owned());
+
+        assert_eq!(ledger.begin("at://a").await.unwrap(), Some(Claim::New));
+        ledger.finish("at://a", failed.clone()).await.unwrap();
+        assert_eq!(ledger.retryable().await.unwrap(), vec!["at://a"]);
+        assert_eq!(ledger.status("at://a").await.unwrap(), Some(failed.clone()));
+
+        assert_eq!(ledger.begin("at://a").await.unwrap(), Some(Claim::Retry));
+        ledger.finish("at://a", failed).await.unwrap();
+        assert!(ledger.retryable().await.unwrap().is_empty());
+        assert_eq!(ledger.begin("at://a").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn delays_retries_of_failed_requests() {
+        let ledger = RequestLedger::open_in_memory(3).unwrap();
+
+        ledger.begin("at://a").await.unwrap();
+        ledger
+            .finish("at://a", RequestStatus::Failed("timeout".to_owned()))
+            .await
+            .unwrap();
+
+        assert!(ledger.retryable().await.unwrap().is_empty());
+        assert_eq!(ledger.begin("at://a").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn retries_interrupted_requests_from_old_ledgers() {
+        let connection = Connection::open_in_memory().unwrap();
+        connection
+            .execute_batch(
+                "CREATE TABLE requests (
+                    uri TEXT PRIMARY KEY NOT NULL,
+                    status TEXT NOT NULL,
+                    error TEXT,
+                    attempts INTEGER NOT NULL,
+                    updated_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+                );
+                INSERT INTO requests (uri, status, attempts) VALUES ('at://a', 'in_progress', 1);",
+            )
+            .unwrap();
+
+        let ledger = RequestLedger::from_connection(connection, 2).unwrap();
+        assert_eq!(
+            ledger.status("at://a").await.unwrap(),
+            Some(RequestStatus::Interrupted)
+        );
+        assert_eq!(ledger.retryable().await.unwrap(), vec!["at://a"]);
+        assert_eq!(ledger.begin("at://a").await.unwrap(), Some(Claim::Retry));
+    }
+}
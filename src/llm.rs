@@ -0,0 +1,70 @@
+use std::fmt::Debug;
+
+use async_trait::async_trait;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+mod canned;
+mod openai;
+
+pub use canned::CannedModel;
+pub use openai::OpenAiChat;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChatRole {
+    System,
+    User,
+    Assistant,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChatMessage {
+    pub role: ChatRole,
+    pub content: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+}
+
+impl ChatMessage {
+    pub fn new(role: ChatRole, content: impl Into<String>) -> Self {
+        Self {
+            role,
+            content: content.into(),
+            name: None,
+        }
+    }
+}
+
+/// A conversation to complete, along with the sampling settings.
+#[derive(Debug, Clone, Default)]
+pub struct ChatRequest {
+    pub messages: Vec<ChatMessage>,
+    /// Identifier of the end user the completion is generated for.
+    pub user: Option<String>,
+    pub max_tokens: Option<u32>,
+    pub temperature: Option<f32>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ChatCompletion {
+    pub content: String,
+    pub total_tokens: Option<u32>,
+}
+
+#[derive(Debug, Error)]
+pub enum LanguageModelError {
+    #[error("Request to the language model failed")]
+    Http(#[from] reqwest::Error),
+    #[error("Language model responded with {status}: {body}")]
+    Api { status: StatusCode, body: String },
+    #[error("Language model returned no completion")]
+    Empty,
+}
+
+/// A backend able to generate responses to a conversation.
+#[async_trait]
+pub trait LanguageModel: Debug + Send + Sync {
+    async fn complete(&self, request: &ChatRequest) -> Result<ChatCompletion, LanguageModelError>;
+}
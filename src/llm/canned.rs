@@ -0,0 +1,68 @@
+use async_trait::async_trait;
+
+use super::{ChatCompletion, ChatRequest, ChatRole, LanguageModel, LanguageModelError};
+
+/// Deterministic backend for testing. It responds with a fixed response, or
+/// echoes the last user message when there is none.
+#[derive(Debug, Clone, Default)]
+pub struct CannedModel {
+    response: Option<String>,
+}
+
+impl CannedModel {
+    pub fn echo() -> Self {
+        Self { response: None }
+    }
+
+    pub fn new(response: impl Into<String>) -> Self {
+        Self {
+            response: Some(response.into()),
+        }
+    }
+}
+
+#[async_trait]
+impl LanguageModel for CannedModel {
+    async fn complete(&self, request: &ChatRequest) -> Result<ChatCompletion, LanguageModelError> {
+        let content = match &self.response {
+            Some(response) => response.clone(),
+            None => request
+                .messages
+                .iter()
+                .rev()
+                .find(|it| it.role == ChatRole::User)
+                .map(|it| it.content.clone())
+                .ok_or(LanguageModelError::Empty)?,
+        };
+
+        Ok(ChatCompletion {
+            content,
+            total_tokens: Some(0),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::ChatMessage;
+
+    #[tokio::test]
+    async fn echoes_last_user_message() {
+        let request = ChatRequest {
+            messages: vec![
+                ChatMessage::new(ChatRole::System, "Be sarcastic"),
+                ChatMessage::new(ChatRole::User, "first"),
+                ChatMessage::new(ChatRole::Assistant, "reply"),
+                ChatMessage::new(ChatRole::User, "second"),
+            ],
+            ..Default::default()
+        };
+
+        let echo = CannedModel::echo().complete(&request).await.unwrap();
+        assert_eq!(echo.content, "second");
+
+        let canned = CannedModel::new("canned").complete(&request).await.unwrap();
+        assert_eq!(canned.content, "canned");
+    }
+}
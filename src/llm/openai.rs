@@ -0,0 +1,119 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use super::{ChatCompletion, ChatMessage, ChatRequest, LanguageModel, LanguageModelError};
+
+pub const DEFAULT_BASE_URL: &str = "https://api.openai.com/v1";
+
+/// Backend for any server implementing the OpenAI chat completions API, which
+/// includes OpenAI itself as well as local servers like llama.cpp and Ollama.
+#[derive(Debug, Clone)]
+pub struct OpenAiChat {
+    http: reqwest::Client,
+    base_url: String,
+    api_key: Option<String>,
+    model: String,
+}
+
+impl OpenAiChat {
+    pub fn new(model: impl Into<String>) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            base_url: DEFAULT_BASE_URL.to_owned(),
+            api_key: None,
+            model: model.into(),
+        }
+    }
+
+    pub fn with_base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = base_url.into().trim_end_matches('/').to_owned();
+        self
+    }
+
+    pub fn with_api_key(mut self, api_key: impl Into<String>) -> Self {
+        self.api_key = Some(api_key.into());
+        self
+    }
+
+    /// Sets how long a completion request may take. Requests don't time out
+    /// by default.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.http = reqwest::Client::builder()
+            .timeout(timeout)
+            .build()
+            .expect("HTTP client should be created");
+        self
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct CompletionRequest<'a> {
+    model: &'a str,
+    messages: &'a [ChatMessage],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    user: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CompletionResponse {
+    choices: Vec<CompletionChoice>,
+    usage: Option<CompletionUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CompletionChoice {
+    message: ChatMessage,
+}
+
+#[derive(Debug, Deserialize)]
+struct CompletionUsage {
+    total_tokens: u32,
+}
+
+#[async_trait]
+impl LanguageModel for OpenAiChat {
+    async fn complete(&self, request: &ChatRequest) -> Result<ChatCompletion, LanguageModelError> {
+        let body = CompletionRequest {
+            model: &self.model,
+            messages: &request.messages,
+            user: request.user.as_deref(),
+            max_tokens: request.max_tokens,
+            temperature: request.temperature,
+        };
+
+        let mut builder = self
+            .http
+            .post(format!("{}/chat/completions", self.base_url))
+            .json(&body);
+
+        if let Some(api_key) = &self.api_key {
+            builder = builder.bearer_auth(api_key);
+        }
+
+        let response = builder.send().await?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(LanguageModelError::Api { status, body });
+        }
+
+        let response = response.json::<CompletionResponse>().await?;
+        let choice = response
+            .choices
+            .into_iter()
+            .next()
+            .ok_or(LanguageModelError::Empty)?;
+
+        Ok(ChatCompletion {
+            content: choice.message.content,
+            total_tokens: response.usage.map(|it| it.total_tokens),
+        })
+    }
+}
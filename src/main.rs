@@ -1,178 +1,160 @@
 pub mod atp;
-
-use std::env;
+mod bot;
+mod config;
+mod ledger;
+mod llm;
+mod persona;
+mod poller;
+mod post_length;
+mod thread;
+
+use std::sync::Arc;
 use std::time::Duration;
 
 use anyhow::Result;
-use atp::{GetPostThreadParams, PostView, XrpcClient};
-use openai::chat::{ChatCompletion, ChatCompletionMessage, ChatCompletionMessageRole};
-use time::format_description::well_known::Iso8601;
-use time::OffsetDateTime;
+use atp::{FileSessionStore, RetryPolicy, XrpcClient};
+use bot::{Bot, BotRequest};
+use config::{Config, LlmBackend, LlmConfig};
+use ledger::RequestLedger;
+use llm::{CannedModel, LanguageModel, OpenAiChat};
+use persona::PersonaRegistry;
+use poller::MentionPoller;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
 use tracing::{event, Level};
 
-use crate::atp::NotificationReason;
-
 #[tokio::main]
 async fn main() -> Result<()> {
-    dotenv::dotenv()?;
+    // The .env file is optional, everything can be set in the config file
+    dotenv::dotenv().ok();
     tracing_subscriber::fmt()
         .with_target(false)
         .with_timer(tracing_subscriber::fmt::time::uptime())
         .with_level(true)
         .init();
 
-    let bs_provider = env::var("BLUESKY_PROVIDER")?;
-    let bs_handle = env::var("BLUESKY_HANDLE")?;
-    let bs_password = env::var("BLUESKY_PASSWORD")?;
-
-    let openai_key = env::var("OPENAI_KEY")?;
-
-    // Setting the OpenAI key for the client
-    // TODO: Switch clients, this is awful
-    openai::set_key(openai_key);
+    let config = Config::load()?;
+    let model = language_model(&config.llm);
 
     // Logging into our client
-    let mut client = XrpcClient::new(&bs_provider).await;
-    client.login(&bs_handle, &bs_password).await?;
-    event!(Level::INFO, "Logged into BlueSky as '{bs_handle}'");
+    let bluesky = &config.bluesky;
+    let password = bluesky.password.clone().unwrap_or_default();
+    let client = XrpcClient::builder(&bluesky.provider)
+        .session_store(FileSessionStore::new(&bluesky.session_file))
+        .retry_policy(RetryPolicy::exponential(bluesky.max_request_attempts))
+        .timeout(Duration::from_secs(bluesky.request_timeout_secs))
+        .build();
+    client.login(&bluesky.handle, password.expose()).await?;
+    event!(Level::INFO, "Logged into BlueSky as '{}'", bluesky.handle);
+
+    let mut poller = MentionPoller::new(client.clone(), &config.bot.state_file)?;
+    let ledger = RequestLedger::open(&config.bot.ledger_file, config.bot.max_process_attempts)?;
+    let personas = PersonaRegistry::load(&config.persona.directory, &config.persona.default)?;
+
+    // Limits how many requests are being processed at the same time
+    let permits = Arc::new(Semaphore::new(config.bot.max_concurrent_requests));
 
-    // TODO: Run this stuff on multiple threads. This requires make the client
-    // capable of being shared accross multiple threads however.
-    //
     // Poll for events on a loop
-    let mut interval = tokio::time::interval(Duration::from_secs(20));
+    let mut interval = tokio::time::interval(Duration::from_secs(config.bot.poll_interval_secs));
+
+    let bot = Bot {
+        client,
+        model,
+        ledger,
+        personas: Arc::new(personas),
+        config: Arc::new(config),
+    };
 
     loop {
         interval.tick().await;
+        bot.personas.reload_if_changed();
 
-        let Ok(events) = poll_events(&mut client).await else {
-            event!(Level::ERROR, "Failed to poll events");
-            continue;
+        let batch = match poller.poll().await {
+            Ok(batch) => batch,
+            Err(e) => {
+                event!(Level::ERROR, "Failed to poll events: {:#}", e);
+                continue;
+            }
         };
 
-        for event in events.into_iter() {
-            let result = process_request(&mut client, event).await;
-
-            if let Err(e) = result {
-                event!(Level::ERROR, "Failed to respond to event: {}", e);
+        // Previously failed requests are retried along with the new mentions
+        let retries = match bot.ledger.retryable().await {
+            Ok(retries) => retries,
+            Err(e) => {
+                event!(Level::ERROR, "Failed to get retryable requests: {:#}", e);
+                Vec::new()
             }
-        }
-    }
-}
-
-#[derive(Debug)]
-struct BotRequest {
-    uri: String,
-}
-
-#[derive(Debug)]
-enum BotRequestResult {
-    Success,
-    InvalidRequest,
-}
-
-async fn poll_events(client: &mut XrpcClient) -> Result<Vec<BotRequest>> {
-    // Getting the instant we will use to read our notifications
-    let now = OffsetDateTime::now_utc().format(&Iso8601::DEFAULT)?;
-
-    // Getting all notifications that are mentions and haven't been read
-    let notifs = client.list_notifications().await?;
-    let notifs = notifs
-        .notifications
-        .into_iter()
-        .filter(|it| it.reason == NotificationReason::Mention && !it.is_read)
-        .map(|it| BotRequest { uri: it.uri })
-        .collect::<Vec<_>>();
-
-    // Marking all the unread notifications as read
-    client.seen_notifications(now).await?;
-
-    event!(Level::INFO, "Polling notifications, {} found", notifs.len());
-
-    Ok(notifs)
-}
-
-async fn process_request(client: &mut XrpcClient, request: BotRequest) -> Result<BotRequestResult> {
-    event!(Level::INFO, "Processing request for {}", request.uri);
-
-    let thread = client
-        .get_post_thread(GetPostThreadParams {
-            uri: request.uri,
-            depth: Some(0),
-        })
-        .await?
-        .thread;
-
-    let Some(child) = thread.post else {
-        event!(Level::WARN, "Invalid request. Child post not found");
-        return Ok(BotRequestResult::InvalidRequest);
-    };
-
-    let Some(parent) = thread.parent.and_then(|it| it.post) else {
-        event!(Level::WARN, "Invalid request. Parent post not found");
-        return Ok(BotRequestResult::InvalidRequest);
-    };
-
-    let Some(response) = generate_response(&parent).await? else {
-        return Ok(BotRequestResult::InvalidRequest);
-    };
+        };
 
-    let mut response = response.chars().take(280).collect::<String>();
-    response.push_str("\n\n🤖 info in bio");
+        let uris = batch
+            .mentions
+            .iter()
+            .map(|it| it.uri.clone())
+            .chain(retries);
+        let mut tasks = JoinSet::new();
+
+        for uri in uris {
+            let event = BotRequest { uri };
+            let bot = bot.clone();
+            let permits = permits.clone();
+
+            tasks.spawn(async move {
+                let _permit = permits.acquire_owned().await?;
+                bot.handle_request(event).await
+            });
+        }
 
-    let reply = client.post_reply(child.uri, child.cid, response).await?;
+        // Wait for the whole batch before polling again
+        let mut unrecorded = 0;
+        while let Some(result) = tasks.join_next().await {
+            match result {
+                Ok(Err(e)) => {
+                    unrecorded += 1;
+                    event!(Level::ERROR, "Failed to record request: {:#}", e);
+                }
+                Err(e) => {
+                    unrecorded += 1;
+                    event!(Level::ERROR, "Request task failed: {}", e);
+                }
+                Ok(Ok(_)) => {}
+            }
+        }
 
-    event!(
-        Level::INFO,
-        "Fulfilled request for {}.\nURI: {}",
-        child.author.handle,
-        reply
-    );
+        // A mention missing from the ledger would never be handled after moving
+        // past it, so the batch is polled again. Recorded mentions are skipped.
+        if unrecorded > 0 {
+            event!(
+                Level::WARN,
+                "{} requests weren't recorded, polling the batch again",
+                unrecorded
+            );
+            continue;
+        }
 
-    Ok(BotRequestResult::Success)
+        // Only moving past the batch once it has been handled
+        if let Err(e) = poller.commit(batch).await {
+            event!(Level::ERROR, "Failed to save poll state: {:#}", e);
+        }
+    }
 }
 
-async fn generate_response(post: &PostView) -> Result<Option<String>> {
-    let system = include_str!("system.txt");
-    let Some(user) = &post.record.text else {
-        return Ok(None);
-    };
+/// Creates the language model backend selected in the configuration.
+fn language_model(config: &LlmConfig) -> Arc<dyn LanguageModel> {
+    match config.backend {
+        LlmBackend::OpenAi => {
+            let mut model = OpenAiChat::new(&config.model)
+                .with_base_url(&config.base_url)
+                .with_timeout(Duration::from_secs(config.request_timeout_secs));
 
-    let prompt = format!("@{}\n{}", post.author.handle, user);
+            if let Some(key) = &config.api_key {
+                model = model.with_api_key(key.expose());
+            }
 
-    let chat = ChatCompletion::builder("gpt-3.5-turbo-0301", [
-        ChatCompletionMessage {
-            role: ChatCompletionMessageRole::System,
-            content: system.to_owned(),
-            name: None,
-        },
-        ChatCompletionMessage {
-            role: ChatCompletionMessageRole::User,
-            content: prompt,
-            name: None,
+            Arc::new(model)
+        }
+        LlmBackend::Canned => match &config.canned_response {
+            Some(response) => Arc::new(CannedModel::new(response)),
+            None => Arc::new(CannedModel::echo()),
         },
-    ])
-    .user(post.author.did.to_owned())
-    .max_tokens(80u32)
-    .temperature(0.7);
-
-    let completion = chat.create().await??;
-    let Some(response) = completion.choices.first() else {
-        return Ok(None);
-    };
-    let message = &response.message.content;
-
-    event!(
-        Level::INFO,
-        "Spent {} tokens generating response of length {} to @{}\n\"{}\"",
-        completion
-            .usage
-            .map(|it| it.total_tokens)
-            .unwrap_or_default(),
-        message.len(),
-        post.author.handle,
-        message,
-    );
-
-    Ok(Some(message.to_owned()))
+    }
 }
@@ -0,0 +1,353 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::SystemTime;
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::Deserialize;
+use tracing::{event, Level};
+
+/// Persona used when the persona directory doesn't exist.
+const BUILTIN_PERSONA: (&str, &str) = ("cody", include_str!("../personas/cody.toml"));
+
+/// Variables which can be used in persona templates.
+const TEMPLATE_VARIABLES: &[&str] = &[
+    "author_handle",
+    "author_display_name",
+    "post_text",
+    "thread_context",
+    "date",
+    "bot_handle",
+];
+
+/// Values for the variables of a template.
+#[derive(Debug, Clone, Default)]
+pub struct PromptContext {
+    pub author_handle: String,
+    pub author_display_name: String,
+    pub post_text: String,
+    pub thread_context: String,
+    pub date: String,
+    pub bot_handle: String,
+}
+
+impl PromptContext {
+    fn get(&self, name: &str) -> &str {
+        match name {
+            "author_handle" => &self.author_handle,
+            "author_display_name" => &self.author_display_name,
+            "post_text" => &self.post_text,
+            "thread_context" => &self.thread_context,
+            "date" => &self.date,
+            "bot_handle" => &self.bot_handle,
+            _ => "",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Text(String),
+    Variable(String),
+}
+
+/// A text with `{{variable}}` placeholders, checked when it's loaded.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    segments: Vec<Segment>,
+}
+
+impl Template {
+    pub fn parse(source: &str) -> Result<Self> {
+        let mut segments = Vec::new();
+        let mut rest = source;
+
+        while let Some(start) = rest.find("{{") {
+            if start > 0 {
+                segments.push(Segment::Text(rest[..start].to_owned()));
+            }
+
+            let end = rest[start..]
+                .find("}}")
+                .ok_or_else(|| anyhow!("Unclosed '{{{{' in template"))?;
+
+            let name = rest[start + 2..start + end].trim();
+            if !TEMPLATE_VARIABLES.contains(&name) {
+                bail!(
+                    "Unknown template variable '{name}', expected one of: {}",
+                    TEMPLATE_VARIABLES.join(", ")
+                );
+            }
+
+            segments.push(Segment::Variable(name.to_owned()));
+            rest = &rest[start + end + 2..];
+        }
+
+        if !rest.is_empty() {
+            segments.push(Segment::Text(rest.to_owned()));
+        }
+
+        Ok(Self { segments })
+    }
+
+    pub fn render(&self, context: &PromptContext) -> String {
+        let rendered = self
+            .segments
+            .iter()
+            .map(|it| match it {
+                Segment::Text(text) => text.as_str(),
+                Segment::Variable(name) => context.get(name),
+            })
+            .collect::<String>();
+
+        rendered.trim().to_owned()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PersonaFile {
+    #[serde(default)]
+    description: String,
+    #[serde(default)]
+    keywords: Vec<String>,
+    system: String,
+    prompt: String,
+}
+
+#[derive(Debug)]
+pub struct Persona {
+    pub name: String,
+    pub description: String,
+    /// The persona is used for mentions containing any of these keywords.
+    pub keywords: Vec<String>,
+    pub system: Template,
+    pub prompt: Template,
+}
+
+impl Persona {
+    fn parse(name: &str, source: &str) -> Result<Self> {
+        let file = toml::from_str::<PersonaFile>(source)?;
+
+        Ok(Self {
+            name: name.to_owned(),
+            description: file.description,
+            keywords: file.keywords.iter().map(|it| it.to_lowercase()).collect(),
+            system: Template::parse(&file.system).context("Invalid system template")?,
+            prompt: Template::parse(&file.prompt).context("Invalid prompt template")?,
+        })
+    }
+}
+
+#[derive(Debug)]
+struct LoadedPersonas {
+    personas: HashMap<String, Arc<Persona>>,
+    modified: Option<SystemTime>,
+}
+
+impl LoadedPersonas {
+    fn log(&self) {
+        for persona in self.personas.values() {
+            event!(
+                Level::INFO,
+                "Persona '{}': {}",
+                persona.name,
+                persona.description
+            );
+        }
+    }
+}
+
+/// The personas in a directory of `<name>.toml` files, reloaded whenever the
+/// directory changes.
+#[derive(Debug)]
+pub struct PersonaRegistry {
+    directory: PathBuf,
+    default: String,
+    loaded: RwLock<LoadedPersonas>,
+}
+
+impl PersonaRegistry {
+    pub fn load(directory: impl Into<PathBuf>, default: impl Into<String>) -> Result<Self> {
+        let directory = directory.into();
+        let default = default.into();
+
+        let loaded = load_personas(&directory)?;
+        if !loaded.personas.contains_key(&default) {
+            bail!(
+                "Default persona '{default}' not found in '{}'",
+                directory.display()
+            );
+        }
+
+        event!(
+            Level::INFO,
+            "Loaded personas from '{}'",
+            directory.display()
+        );
+        loaded.log();
+
+        Ok(Self {
+            directory,
+            default,
+            loaded: RwLock::new(loaded),
+        })
+    }
+
+    /// Reloads the personas if any file in the directory changed. The current
+    /// personas are kept when the new ones fail to load.
+    pub fn reload_if_changed(&self) {
+        let modified = last_modified(&self.directory);
+        if modified == self.loaded().modified {
+            return;
+        }
+
+        match load_personas(&self.directory) {
+            Ok(loaded) if loaded.personas.contains_key(&self.default) => {
+                event!(Level::INFO, "Reloaded personas");
+                loaded.log();
+                *self
+                    .loaded
+                    .write()
+                    .expect("Persona lock should not be poisoned") = loaded;
+            }
+            Ok(_) => event!(
+                Level::ERROR,
+                "Not reloading personas, default persona '{}' is missing",
+                self.default
+            ),
+            Err(e) => event!(Level::ERROR, "Failed to reload personas: {:#}", e),
+        }
+    }
+
+    /// Picks the persona for a mention, which is the first persona with a
+    /// keyword in the text, or the default persona.
+    pub fn select(&self, text: &str) -> Arc<Persona> {
+        let text = text.to_lowercase();
+        let loaded = self.loaded();
+
+        let mut personas = loaded.personas.values().collect::<Vec<_>>();
+        personas.sort_by(|a, b| a.name.cmp(&b.name));
+
+        personas
+            .into_iter()
+            .find(|it| it.keywords.iter().any(|keyword| text.contains(keyword)))
+            .or_else(|| loaded.personas.get(&self.default))
+            .cloned()
+            .expect("Default persona should be loaded")
+    }
+
+    fn loaded(&self) -> std::sync::RwLockReadGuard<'_, LoadedPersonas> {
+        self.loaded
+            .read()
+            .expect("Persona lock should not be poisoned")
+    }
+}
+
+fn load_personas(directory: &Path) -> Result<LoadedPersonas> {
+    let mut personas = HashMap::new();
+
+    if !directory.exists() {
+        let (name, source) = BUILTIN_PERSONA;
+        personas.insert(name.to_owned(), Arc::new(Persona::parse(name, source)?));
+
+        return Ok(LoadedPersonas {
+            personas,
+            modified: None,
+        });
+    }
+
+    let entries = fs::read_dir(directory)
+        .with_context(|| format!("Failed to read '{}'", directory.display()))?;
+
+    for entry in entries {
+        let path = entry?.path();
+        if path.extension().and_then(|it| it.to_str()) != Some("toml") {
+            continue;
+        }
+
+        let Some(name) = path.file_stem().and_then(|it| it.to_str()) else {
+            continue;
+        };
+
+        let source = fs::read_to_string(&path)?;
+        let persona = Persona::parse(name, &source)
+            .with_context(|| format!("Invalid persona '{}'", path.display()))?;
+
+        personas.insert(name.to_owned(), Arc::new(persona));
+    }
+
+    Ok(LoadedPersonas {
+        personas,
+        modified: last_modified(directory),
+    })
+}
+
+/// The latest modification time of the directory and the files in it.
+fn last_modified(directory: &Path) -> Option<SystemTime> {
+    let modified = |path: &Path| fs::metadata(path).and_then(|it| it.modified()).ok();
+
+    let files = fs::read_dir(directory)
+        .ok()?
+        .filter_map(|it| it.ok())
+        .filter_map(|it| modified(&it.path()));
+
+    files.chain(modified(directory)).max()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_template_variables() {
+        let template = Template::parse("@{{ author_handle }} said:\n{{post_text}}\n").unwrap();
+        let context = PromptContext {
+            author_handle: "alice.bsky.social".to_owned(),
+            post_text: "Hello {{world}}".to_owned(),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            template.render(&context),
+            "@alice.bsky.social said:\nHello {{world}}"
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_variables() {
+        assert!(Template::parse("{{author}}").is_err());
+        assert!(Template::parse("{{post_text").is_err());
+    }
+
+    #[test]
+    fn selects_persona_by_keyword() {
+        let mut personas = HashMap::new();
+        for (name, keywords) in [("cody", "[]"), ("poet", "[\"#Poem\"]")] {
+            let source = format!("keywords = {keywords}\nsystem = \"\"\nprompt = \"\"");
+            personas.insert(
+                name.to_owned(),
+                Arc::new(Persona::parse(name, &source).unwrap()),
+            );
+        }
+
+        let registry = PersonaRegistry {
+            directory: PathBuf::new(),
+            default: "cody".to_owned(),
+            loaded: RwLock::new(LoadedPersonas {
+                personas,
+                modified: None,
+            }),
+        };
+
+        assert_eq!(registry.select("@bot write a #poem").name, "poet");
+        assert_eq!(registry.select("@bot roast this").name, "cody");
+    }
+
+    #[test]
+    fn parses_builtin_persona() {
+        let (name, source) = BUILTIN_PERSONA;
+        assert!(Persona::parse(name, source).is_ok());
+    }
+}
@@ -0,0 +1,252 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+use anyhow::Result;
+use futures::{future, TryStreamExt};
+use serde::{Deserialize, Serialize};
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+use tracing::{event, Level};
+
+use crate::atp::{
+    ListNotificationsParams, Notification, NotificationReason, PageOptions, XrpcClient,
+};
+
+const NOTIFICATION_PAGE_SIZE: u32 = 100;
+
+/// Position of the newest notification that has been handled.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PollCursor {
+    /// `indexedAt` of the newest handled notification, exactly as it was sent
+    /// by the server.
+    indexed_at: String,
+    /// Handled notifications that were indexed at exactly `indexed_at`, so
+    /// they aren't handled again when a notification with the same timestamp
+    /// shows up later.
+    uris: Vec<String>,
+}
+
+impl PollCursor {
+    fn time(&self) -> Option<OffsetDateTime> {
+        parse_time(&self.indexed_at)
+    }
+}
+
+/// Notifications found by a poll. The poller only moves past them once the
+/// batch is committed.
+#[derive(Debug)]
+pub struct PollBatch {
+    pub mentions: Vec<Notification>,
+    cursor: Option<PollCursor>,
+}
+
+/// Polls for new mentions, keeping track of the newest handled notification
+/// in a state file so no mention is skipped or handled twice across polls and
+/// restarts.
+#[derive(Debug)]
+pub struct MentionPoller {
+    client: XrpcClient,
+    state_path: PathBuf,
+    cursor: Option<PollCursor>,
+}
+
+impl MentionPoller {
+    pub fn new(client: XrpcClient, state_path: impl Into<PathBuf>) -> Result<Self> {
+        let state_path = state_path.into();
+
+        let cursor = match fs::read_to_string(&state_path) {
+            Ok(contents) => Some(serde_json::from_str(&contents)?),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(Self {
+            client,
+            state_path,
+            cursor,
+        })
+    }
+
+    /// Fetches every notification newer than the cursor. Without a cursor,
+    /// which is only the case on the very first run, the unread notifications
+    /// are fetched instead.
+    pub async fn poll(&self) -> Result<PollBatch> {
+        let last = self.cursor.as_ref().and_then(PollCursor::time);
+        let options = PageOptions::default().page_size(NOTIFICATION_PAGE_SIZE);
+
+        // Notifications are sorted newest first, so we can stop at the first one
+        // older than the cursor.
+        let params = ListNotificationsParams {
+            limit: None,
+            cursor: None,
+            seen_at: None,
+        };
+        let notifications = self
+            .client
+            .notifications(params, options)
+            .try_take_while(|it| {
+                let newer = match last {
+                    Some(last) => parse_time(&it.indexed_at).is_none_or(|t| t >= last),
+                    None => !it.is_read,
+                };
+
+                future::ready(Ok(newer))
+            })
+            .try_filter(|it| future::ready(self.is_new(it)))
+            .try_collect::<Vec<_>>()
+            .await?;
+
+        let cursor = self.next_cursor(&notifications);
+        let mentions = notifications
+            .into_iter()
+            .filter(|it| it.reason == NotificationReason::Mention)
+            .collect::<Vec<_>>();
+
+        event!(
+            Level::INFO,
+            "Polling notifications, {} found",
+            mentions.len()
+        );
+
+        Ok(PollBatch { mentions, cursor })
+    }
+
+    /// Moves the cursor past a batch after it has been handled, and marks
+    /// the notifications up to the cursor as seen.
+    pub async fn commit(&mut self, batch: PollBatch) -> Result<()> {
+        let Some(cursor) = batch.cursor else {
+            return Ok(());
+        };
+
+        if self.cursor.as_ref() == Some(&cursor) {
+            return Ok(());
+        }
+
+        // Persisting first as the state file, not the seen marker, is what
+        // decides which notifications get handled.
+        let temp = self.state_path.with_extension("tmp");
+        let mut file = fs::File::create(&temp)?;
+        file.write_all(&serde_json::to_vec_pretty(&cursor)?)?;
+        file.sync_all()?;
+        fs::rename(&temp, &self.state_path)?;
+
+        let indexed_at = cursor.indexed_at.clone();
+        self.cursor = Some(cursor);
+
+        if let Err(e) = self.client.seen_notifications(indexed_at).await {
+            event!(Level::WARN, "Failed to mark notifications as seen: {:#}", e);
+        }
+
+        Ok(())
+    }
+
+    fn is_new(&self, notification: &Notification) -> bool {
+        let Some(cursor) = &self.cursor else {
+            return true;
+        };
+
+        let Some(time) = parse_time(&notification.indexed_at) else {
+            event!(
+                Level::WARN,
+                "Skipping notification {} with invalid timestamp '{}'",
+                notification.uri,
+                notification.indexed_at
+            );
+            return false;
+        };
+
+        match cursor.time() {
+            Some(last) if time == last => !cursor.uris.contains(&notification.uri),
+            Some(last) => time > last,
+            None => true,
+        }
+    }
+
+    /// The cursor after handling `notifications`, which are all newer than the
+    /// current cursor.
+    fn next_cursor(&self, notifications: &[Notification]) -> Option<PollCursor> {
+        let newest = notifications
+            .iter()
+            .filter_map(|it| parse_time(&it.indexed_at).map(|time| (time, it)))
+            .max_by_key(|(time, _)| *time);
+
+        let Some((time, newest)) = newest else {
+            return self.cursor.clone();
+        };
+
+        let mut uris = notifications
+            .iter()
+            .filter(|it| parse_time(&it.indexed_at) == Some(time))
+            .map(|it| it.uri.clone())
+            .collect::<Vec<_>>();
+
+        // Keeping the previous boundary when the newest notifications share its
+        // timestamp.
+        if let Some(cursor) = &self.cursor {
+            if cursor.time() == Some(time) {
+                uris.extend(cursor.uris.iter().cloned());
+            }
+        }
+
+        Some(PollCursor {
+            indexed_at: newest.indexed_at.clone(),
+            uris,
+        })
+    }
+}
+
+fn parse_time(value: &str) -> Option<OffsetDateTime> {
+    OffsetDateTime::parse(value, &Rfc3339).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn notification(uri: &str, indexed_at: &str) -> Notification {
+        serde_json::from_value(json!({
+            "uri": uri,
+            "cid": "bafyrei",
+            "author": { "did": "did:plc:alice", "handle": "alice.bsky.social" },
+            "reason": "mention",
+            "record": {
+                "$type": "app.bsky.feed.post",
+                "text": "@bot hi",
+                "createdAt": indexed_at,
+            },
+            "isRead": false,
+            "indexedAt": indexed_at,
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn skips_handled_notifications_sharing_the_cursor_timestamp() {
+        let mut poller = MentionPoller {
+            client: XrpcClient::new("http://localhost").await,
+            state_path: PathBuf::from("state.json"),
+            cursor: None,
+        };
+
+        let first = notification("at://a", "2023-05-13T17:46:40.000Z");
+        poller.cursor = poller.next_cursor(&[first]);
+
+        let handled = notification("at://a", "2023-05-13T17:46:40Z");
+        let same_time = notification("at://b", "2023-05-13T17:46:40.000Z");
+        let older = notification("at://c", "2023-05-13T17:46:39.999Z");
+        let newer = notification("at://d", "2023-05-13T17:46:41.000Z");
+
+        assert!(!poller.is_new(&handled));
+        assert!(poller.is_new(&same_time));
+        assert!(!poller.is_new(&older));
+        assert!(poller.is_new(&newer));
+
+        let cursor = poller.next_cursor(&[same_time]).unwrap();
+        assert_eq!(cursor.indexed_at, "2023-05-13T17:46:40.000Z");
+        assert_eq!(cursor.uris, vec!["at://b", "at://a"]);
+    }
+}
@@ -0,0 +1,215 @@
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Longest text of an `app.bsky.feed.post`, in graphemes.
+pub const MAX_POST_GRAPHEMES: usize = 300;
+
+/// Longest text of an `app.bsky.feed.post`, in UTF-8 bytes.
+pub const MAX_POST_BYTES: usize = 3000;
+
+const ELLIPSIS: &str = "…";
+
+/// Counts graphemes the same way the `maxGraphemes` lexicon constraint does.
+pub fn grapheme_count(text: &str) -> usize {
+    text.graphemes(true).count()
+}
+
+/// Splits a response into numbered posts of at most `max_graphemes` each, with
+/// the signature on the last one. Whatever doesn't fit in `max_parts` posts is
+/// truncated, and a response which fits in one post isn't numbered.
+pub fn split_with_signature(
+    response: &str,
+    signature: &str,
+    max_graphemes: usize,
+    max_parts: usize,
+) -> Vec<String> {
+    let response = response.trim();
+    let (max_graphemes, max_bytes) = limits(signature, max_graphemes);
+
+    if max_parts <= 1 || prefix_end(response, max_graphemes, max_bytes) == response.len() {
+        let mut text = truncate(response, max_graphemes, max_bytes);
+        text.push_str(signature);
+        return vec![text];
+    }
+
+    // Trying as few parts as possible, as the numbering takes up room too.
+    for parts in 2..=max_parts {
+        let label = part_label(parts, parts);
+        let max_graphemes = max_graphemes.saturating_sub(grapheme_count(&label));
+        let max_bytes = max_bytes.saturating_sub(label.len());
+        let fits = |text: &str| prefix_end(text, max_graphemes, max_bytes) == text.len();
+
+        let mut chunks = Vec::new();
+        let mut rest = response;
+        while chunks.len() + 1 < parts && !fits(rest) {
+            let end = split_point(rest, max_graphemes, max_bytes);
+            chunks.push(rest[..end].trim_end().to_owned());
+            rest = rest[end..].trim_start();
+        }
+
+        if !fits(rest) && parts < max_parts {
+            continue;
+        }
+
+        chunks.push(truncate(rest, max_graphemes, max_bytes));
+
+        let count = chunks.len();
+        for (i, chunk) in chunks.iter_mut().enumerate() {
+            chunk.push_str(&part_label(i + 1, count));
+        }
+        if let Some(last) = chunks.last_mut() {
+            last.push_str(signature);
+        }
+
+        return chunks;
+    }
+
+    unreachable!("The last attempt uses every part")
+}
+
+/// Shortens `text` to at most `max_graphemes` graphemes and `max_bytes` bytes.
+/// Text is cut after the last sentence that fits when that keeps most of it,
+/// otherwise after the last word with an ellipsis.
+pub fn truncate(text: &str, max_graphemes: usize, max_bytes: usize) -> String {
+    let text = text.trim();
+    let end = prefix_end(text, max_graphemes, max_bytes);
+    if end == text.len() {
+        return text.to_owned();
+    }
+
+    if let Some(sentence_end) = sentence_end(text, end) {
+        return text[..sentence_end].to_owned();
+    }
+
+    let end = prefix_end(
+        text,
+        max_graphemes.saturating_sub(1),
+        max_bytes.saturating_sub(ELLIPSIS.len()),
+    );
+
+    let cut = text[..word_end(text, end).unwrap_or(end)].trim_end_matches(|c: char| {
+        c.is_whitespace() || matches!(c, ',' | ';' | ':' | '-' | '(' | '"')
+    });
+
+    if cut.is_empty() {
+        return String::new();
+    }
+
+    format!("{cut}{ELLIPSIS}")
+}
+
+/// Limits for a response which is followed by `signature`.
+fn limits(signature: &str, max_graphemes: usize) -> (usize, usize) {
+    let max_graphemes =
+        max_graphemes.min(MAX_POST_GRAPHEMES.saturating_sub(grapheme_count(signature)));
+    let max_bytes = MAX_POST_BYTES.saturating_sub(signature.len());
+
+    (max_graphemes, max_bytes)
+}
+
+fn part_label(part: usize, parts: usize) -> String {
+    format!(" ({part}/{parts})")
+}
+
+/// Where to split `text` so the first part fits in the limits, preferring
+/// sentence and then word boundaries.
+fn split_point(text: &str, max_graphemes: usize, max_bytes: usize) -> usize {
+    let end = prefix_end(text, max_graphemes, max_bytes);
+    if end == 0 {
+        // Always making progress, even if a single grapheme doesn't fit.
+        return text.graphemes(true).next().map_or(0, str::len);
+    }
+
+    sentence_end(text, end)
+        .or_else(|| word_end(text, end))
+        .unwrap_or(end)
+}
+
+// Cutting in the middle of a long sentence or word is better than dropping
+// most of the text, so boundaries in the first half are ignored.
+
+/// End of the last sentence before `end`.
+fn sentence_end(text: &str, end: usize) -> Option<usize> {
+    text.split_sentence_bound_indices()
+        .map(|(i, sentence)| i + sentence.trim_end().len())
+        .take_while(|&it| it <= end)
+        .last()
+        .filter(|&it| it > end / 2 && it > 0)
+}
+
+/// End of the last word before `end`.
+fn word_end(text: &str, end: usize) -> Option<usize> {
+    text.split_word_bound_indices()
+        .filter(|(_, word)| word.chars().any(char::is_alphanumeric))
+        .map(|(i, word)| i + word.len())
+        .take_while(|&it| it <= end)
+        .last()
+        .filter(|&it| it > end / 2)
+}
+
+/// Byte index of the end of the longest prefix within the limits.
+fn prefix_end(text: &str, max_graphemes: usize, max_bytes: usize) -> usize {
+    text.grapheme_indices(true)
+        .map(|(i, grapheme)| i + grapheme.len())
+        .take(max_graphemes)
+        .take_while(|&it| it <= max_bytes)
+        .last()
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_text_that_fits() {
+        assert_eq!(truncate("  Short enough. ", 20, 100), "Short enough.");
+        assert_eq!(grapheme_count("👨‍👩‍👧 é"), 3);
+    }
+
+    #[test]
+    fn cuts_at_sentence_or_word_boundaries() {
+        let text = "This is fine. But this part is far too long for the post";
+
+        assert_eq!(truncate(text, 20, 100), "This is fine.");
+        assert_eq!(
+            truncate(text, 40, 100),
+            "This is fine. But this part is far too…"
+        );
+        assert_eq!(truncate("Supercalifragilistic", 6, 100), "Super…");
+    }
+
+    #[test]
+    fn never_splits_graphemes() {
+        let text = "👨‍👩‍👧👨‍👩‍👧👨‍👩‍👧";
+
+        assert_eq!(truncate(text, 2, 100), "👨‍👩‍👧…");
+        assert_eq!(truncate(text, 10, 21), "👨‍👩‍👧…");
+    }
+
+    #[test]
+    fn reserves_room_for_the_signature() {
+        let response = "word ".repeat(100);
+        let post = split_with_signature(&response, "\n\n🤖 bot", MAX_POST_GRAPHEMES, 1).remove(0);
+
+        assert!(grapheme_count(&post) <= MAX_POST_GRAPHEMES);
+        assert!(post.ends_with("word…\n\n🤖 bot"));
+    }
+
+    #[test]
+    fn splits_into_numbered_parts() {
+        let response = "One two three. Four five six seven. Eight nine.";
+
+        assert_eq!(split_with_signature(response, " 🤖", 24, 3), vec![
+            "One two three. (1/3)",
+            "Four five six (2/3)",
+            "seven. Eight nine. (3/3) 🤖"
+        ]);
+        assert_eq!(split_with_signature(response, " 🤖", 24, 2), vec![
+            "One two three. (1/2)",
+            "Four five six… (2/2) 🤖"
+        ]);
+        assert_eq!(split_with_signature(response, " 🤖", 100, 3), vec![
+            format!("{response} 🤖")
+        ]);
+    }
+}
@@ -0,0 +1,253 @@
+use crate::atp::{PostView, ThreadView, ThreadViewPost};
+use crate::llm::{ChatMessage, ChatRole};
+
+/// Limits on how much of a thread is given to the model.
+#[derive(Debug, Clone, Copy)]
+pub struct ThreadLimits {
+    /// Most posts above the mention to include.
+    pub max_depth: usize,
+    /// Rough budget for the history, in tokens.
+    pub max_tokens: usize,
+}
+
+/// A thread leading up to a mention, split into the post the bot responds to
+/// and the earlier posts as history.
+#[derive(Debug)]
+pub struct Conversation {
+    /// Posts before the target, oldest first.
+    pub history: Vec<PostView>,
+    pub target: PostView,
+}
+
+impl Conversation {
+    /// Builds the conversation for the mention at the top of `thread`, which
+    /// has to be a reply. The target is the post the mention replies to, or
+    /// the mention itself when it replies to the bot. The thread ends at the
+    /// first deleted or blocked post, and the oldest posts that don't fit in
+    /// the limits are left out.
+    pub fn from_thread(
+        thread: ThreadViewPost,
+        bot_did: &str,
+        limits: ThreadLimits,
+    ) -> Option<Self> {
+        let mention = thread.post;
+
+        let mut posts = Vec::new();
+        let mut parent = thread.parent;
+        while let Some(view) = parent {
+            let ThreadView::Post(view) = *view else {
+                break;
+            };
+
+            if posts.len() >= limits.max_depth {
+                break;
+            }
+
+            if view.post.record.text().is_some() {
+                posts.push(view.post);
+            }
+            parent = view.parent;
+        }
+        posts.reverse();
+
+        if posts.last()?.author.did == bot_did {
+            posts.push(mention);
+        }
+
+        let target = posts.pop()?;
+
+        // Dropping the oldest posts until the rest fits in the budget.
+        let mut tokens = 0;
+        let keep = posts
+            .iter()
+            .rev()
+            .take_while(|it| {
+                tokens += estimate_tokens(&render_post(it));
+                tokens <= limits.max_tokens
+            })
+            .count();
+        posts.drain(..posts.len() - keep);
+
+        Some(Self {
+            history: posts,
+            target,
+        })
+    }
+
+    /// The history as chat turns, with the bot's own posts as assistant turns.
+    pub fn messages(&self, bot_did: &str) -> Vec<ChatMessage> {
+        self.history
+            .iter()
+            .map(|it| {
+                if it.author.did == bot_did {
+                    let text = it.record.text().unwrap_or_default();
+                    ChatMessage::new(ChatRole::Assistant, text)
+                } else {
+                    ChatMessage::new(ChatRole::User, render_post(it))
+                }
+            })
+            .collect()
+    }
+
+    /// The history as plain text, for prompt templates.
+    pub fn transcript(&self) -> String {
+        self.history
+            .iter()
+            .map(render_post)
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+}
+
+fn render_post(post: &PostView) -> String {
+    format!(
+        "@{}\n{}",
+        post.author.handle,
+        post.record.text().unwrap_or_default()
+    )
+}
+
+/// Approximates the token count of a text, at about four characters a token.
+fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(4)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    const BOT: &str = "did:plc:bot";
+
+    fn post(did: &str, text: &str) -> Value {
+        json!({
+            "uri": format!("at://{did}/app.bsky.feed.post/{}", text.len()),
+            "cid": "bafyrei",
+            "author": { "did": did, "handle": "someone.bsky.social" },
+            "record": {
+                "$type": "app.bsky.feed.post",
+                "text": text,
+                "createdAt": "2023-05-13T17:46:40.000Z",
+            },
+        })
+    }
+
+    fn thread_view(post: Value, parent: Value) -> Value {
+        json!({
+            "$type": "app.bsky.feed.defs#threadViewPost",
+            "post": post,
+            "parent": parent,
+        })
+    }
+
+    /// A thread with `posts` as the parents of the mention, oldest first.
+    /// Deleted posts are passed as `notFoundPost` views.
+    fn thread(posts: Vec<Value>, mention: Value) -> ThreadViewPost {
+        let mut thread = Value::Null;
+        for post in posts {
+            thread = match post.get("notFound") {
+                Some(_) => post,
+                None => thread_view(post, thread),
+            };
+        }
+
+        serde_json::from_value(thread_view(mention, thread)).unwrap()
+    }
+
+    fn texts(conversation: &Conversation) -> Vec<&str> {
+        conversation
+            .history
+            .iter()
+            .chain([&conversation.target])
+            .map(|it| it.record.text().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn stops_at_missing_parent() {
+        let limits = ThreadLimits {
+            max_depth: 10,
+            max_tokens: 1000,
+        };
+        let thread = thread(
+            vec![
+                post("did:plc:a", "root"),
+                json!({
+                    "$type": "app.bsky.feed.defs#notFoundPost",
+                    "uri": "at://deleted",
+                    "notFound": true,
+                }),
+                post("did:plc:a", "question"),
+                post(BOT, "bot answer"),
+                post("did:plc:b", "parent"),
+            ],
+            post("did:plc:c", "@bot"),
+        );
+
+        let conversation = Conversation::from_thread(thread, BOT, limits).unwrap();
+        assert_eq!(texts(&conversation), vec![
+            "question",
+            "bot answer",
+            "parent"
+        ]);
+
+        let roles = conversation
+            .messages(BOT)
+            .iter()
+            .map(|it| it.role)
+            .collect::<Vec<_>>();
+        assert_eq!(roles, vec![ChatRole::User, ChatRole::Assistant]);
+    }
+
+    #[test]
+    fn targets_the_mention_when_replying_to_the_bot() {
+        let limits = ThreadLimits {
+            max_depth: 10,
+            max_tokens: 1000,
+        };
+        let thread = thread(
+            vec![post("did:plc:a", "root"), post(BOT, "bot answer")],
+            post("did:plc:a", "@bot why?"),
+        );
+
+        let conversation = Conversation::from_thread(thread, BOT, limits).unwrap();
+        assert_eq!(texts(&conversation), vec![
+            "root",
+            "bot answer",
+            "@bot why?"
+        ]);
+    }
+
+    #[test]
+    fn drops_the_oldest_posts_over_the_limits() {
+        let posts = vec![
+            post("did:plc:a", "first"),
+            post("did:plc:a", "second"),
+            post("did:plc:a", "third"),
+            post("did:plc:a", "fourth"),
+        ];
+
+        let limits = ThreadLimits {
+            max_depth: 3,
+            max_tokens: 1000,
+        };
+        let conversation = Conversation::from_thread(
+            thread(posts.clone(), post("did:plc:b", "@bot")),
+            BOT,
+            limits,
+        )
+        .unwrap();
+        assert_eq!(texts(&conversation), vec!["second", "third", "fourth"]);
+
+        // "@someone.bsky.social\nthird" is 7 tokens.
+        let limits = ThreadLimits {
+            max_depth: 10,
+            max_tokens: 10,
+        };
+        let conversation =
+            Conversation::from_thread(thread(posts, post("did:plc:b", "@bot")), BOT, limits)
+                .unwrap();
+        assert_eq!(texts(&conversation), vec!["third", "fourth"]);
+    }
+}